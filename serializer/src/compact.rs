@@ -0,0 +1,572 @@
+// The layout of format version 2 and up. Bid and trick fields are sized by the number of cards
+// and players of each round, so every player count the 3 bit n_players field allows fits.
+//
+// Version 2: card counts follow the default schedule, bids use the legacy per round bit fields.
+// Version 3: the schedule is stored, bids take just enough bits for the round's card count.
+// Version 4: no layout changes, the header gained a checksum.
+// Version 5: every bid and trick count has a presence bit instead of one current_round for all.
+// Version 6: the scoring variant is stored, older games use the standard rules.
+
+use deku::bitvec::{BitSlice, BitVec, Msb0};
+use deku::ctx::{BitSize, Endian};
+use deku::prelude::*;
+use crate::legacy::PlayerScore;
+use crate::schedule::{Schedule, MAX_CARDS};
+use crate::score::Scoring;
+use crate::{calculate_needed_bits, deserialize_tricks, name_bytes, serialize_tricks, JsGame, JsPlayer, JsRound, Player, SerializeError, Trump, MAX_PLAYERS, MAX_ROUNDS};
+
+const SCHEDULE_VERSION: u8 = 3;
+const PRESENCE_VERSION: u8 = 5;
+const SCORING_VERSION: u8 = 6;
+
+type ReadResult<'a, T> = Result<(&'a BitSlice<u8, Msb0>, T), DekuError>;
+
+#[derive(Debug, PartialEq, DekuRead, DekuWrite)]
+#[deku(ctx = "version: u8")]
+pub(crate) struct Game {
+    name_length: u8,
+    #[deku(count = "name_length")]
+    name: Vec<u8>,
+    #[deku(bits = "3")]
+    n_players: u8,
+    #[deku(count = "n_players")]
+    players: Vec<Player>,
+    #[deku(bits = "3")]
+    start_dealer: u8,
+    #[deku(bits = "5")]
+    n_rounds: u8,
+    // bids of rounds before current_round and tricks of the rounds before that are present
+    #[deku(
+        bits = "5",
+        cond = "version < PRESENCE_VERSION",
+        writer = "write_current_round(deku::output, version, self.current_round)"
+    )]
+    current_round: u8,
+    #[deku(
+        cond = "version >= SCHEDULE_VERSION",
+        default = "Schedule::default()",
+        ctx = "*n_rounds",
+        writer = "write_schedule(deku::output, version, &self.schedule, self.n_rounds)"
+    )]
+    schedule: Schedule,
+    #[deku(
+        cond = "version >= SCORING_VERSION",
+        default = "Scoring::default()",
+        writer = "write_scoring(deku::output, version, &self.scoring)"
+    )]
+    scoring: Scoring,
+    #[deku(count = "n_rounds")]
+    trumps: Vec<Trump>,
+    // bids per player per round
+    #[deku(
+        reader = "read_bids(deku::rest, version, *n_players, schedule, *n_rounds, *current_round)",
+        writer = "write_bids(deku::output, version, &self.bids, &self.schedule, self.n_rounds)"
+    )]
+    bids: Vec<Vec<Option<u8>>>,
+    // tricks per round per player
+    #[deku(
+        reader = "read_tricks(deku::rest, version, *n_players, schedule, *n_rounds, *current_round)",
+        writer = "write_tricks(deku::output, version, &self.tricks, self.n_players, &self.schedule, self.n_rounds)"
+    )]
+    tricks: Vec<Vec<Option<u8>>>,
+}
+
+// deku writes `cond` fields unconditionally
+fn write_current_round(output: &mut BitVec<u8, Msb0>, version: u8, current_round: u8) -> Result<(), DekuError> {
+    if version >= PRESENCE_VERSION {
+        return Ok(());
+    }
+    write_bits(output, current_round as u32, 5)
+}
+
+fn write_schedule(output: &mut BitVec<u8, Msb0>, version: u8, schedule: &Schedule, n_rounds: u8) -> Result<(), DekuError> {
+    if version < SCHEDULE_VERSION {
+        return Ok(());
+    }
+    schedule.write(output, n_rounds)
+}
+
+fn write_scoring(output: &mut BitVec<u8, Msb0>, version: u8, scoring: &Scoring) -> Result<(), DekuError> {
+    if version < SCORING_VERSION {
+        return Ok(());
+    }
+    scoring.write(output, ())
+}
+
+fn round_cards(schedule: &Schedule, n_rounds: u8) -> Result<Vec<u8>, DekuError> {
+    let mut cards = schedule.cards();
+    if cards.len() < n_rounds as usize {
+        return Err(DekuError::Parse(format!("schedule has {} rounds, game has {}", cards.len(), n_rounds)));
+    }
+    cards.truncate(n_rounds as usize);
+    Ok(cards)
+}
+
+fn read_bits(rest: &BitSlice<u8, Msb0>, bits: usize) -> ReadResult<'_, u32> {
+    if bits == 0 {
+        return Ok((rest, 0));
+    }
+    u32::read(rest, (Endian::Big, BitSize(bits)))
+}
+
+fn write_bits(output: &mut BitVec<u8, Msb0>, value: u32, bits: usize) -> Result<(), DekuError> {
+    if bits == 0 {
+        return Ok(());
+    }
+    value.write(output, (Endian::Big, BitSize(bits)))
+}
+
+fn read_present(rest: &BitSlice<u8, Msb0>) -> ReadResult<'_, bool> {
+    let (rest, present) = read_bits(rest, 1)?;
+    Ok((rest, present == 1))
+}
+
+fn read_bids<'a>(rest: &'a BitSlice<u8, Msb0>, version: u8, n_players: u8, schedule: &Schedule, n_rounds: u8, current_round: u8) -> ReadResult<'a, Vec<Vec<Option<u8>>>> {
+    let cards = round_cards(schedule, n_rounds)?;
+    let mut rest = rest;
+    let mut bids = Vec::with_capacity(n_players as usize);
+    for _ in 0..n_players {
+        let mut player_bids = Vec::with_capacity(cards.len());
+        if version < SCHEDULE_VERSION {
+            let (new_rest, score) = PlayerScore::read(rest, ())?;
+            rest = new_rest;
+            for round in 1..=n_rounds {
+                player_bids.push(Some(score.get_round(round)).filter(|_| round <= current_round));
+            }
+        } else {
+            for (round_index, n_cards) in cards.iter().enumerate() {
+                let present = if version < PRESENCE_VERSION {
+                    round_index < current_round as usize
+                } else {
+                    let (new_rest, present) = read_present(rest)?;
+                    rest = new_rest;
+                    present
+                };
+
+                if version < PRESENCE_VERSION || present {
+                    let (new_rest, bid) = read_bits(rest, calculate_needed_bits(*n_cards) as usize)?;
+                    rest = new_rest;
+                    player_bids.push(Some(bid as u8).filter(|_| present));
+                } else {
+                    player_bids.push(None);
+                }
+            }
+        }
+        bids.push(player_bids);
+    }
+    Ok((rest, bids))
+}
+
+fn write_bids(output: &mut BitVec<u8, Msb0>, version: u8, bids: &[Vec<Option<u8>>], schedule: &Schedule, n_rounds: u8) -> Result<(), DekuError> {
+    let cards = round_cards(schedule, n_rounds)?;
+    for player_bids in bids {
+        if version < SCHEDULE_VERSION {
+            let mut score = PlayerScore::default();
+            for (round_index, bid) in player_bids.iter().enumerate() {
+                score.set_round(round_index as u8 + 1, bid.unwrap_or(0));
+            }
+            score.write(output, ())?;
+        } else {
+            for (bid, n_cards) in player_bids.iter().zip(&cards) {
+                if version >= PRESENCE_VERSION {
+                    write_bits(output, bid.is_some() as u32, 1)?;
+                }
+                if version < PRESENCE_VERSION || bid.is_some() {
+                    write_bits(output, bid.unwrap_or(0) as u32, calculate_needed_bits(*n_cards) as usize)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_tricks<'a>(rest: &'a BitSlice<u8, Msb0>, version: u8, n_players: u8, schedule: &Schedule, n_rounds: u8, current_round: u8) -> ReadResult<'a, Vec<Vec<Option<u8>>>> {
+    let cards = round_cards(schedule, n_rounds)?;
+    let mut rest = rest;
+    let mut tricks = Vec::with_capacity(n_rounds as usize);
+    for (round_index, n_cards) in cards.into_iter().enumerate() {
+        if version < PRESENCE_VERSION {
+            let (new_rest, value) = read_bits(rest, (n_cards + n_players) as usize)?;
+            rest = new_rest;
+            if round_index + 1 < current_round as usize {
+                tricks.push(deserialize_tricks(value).into_iter().map(Some).collect());
+            } else {
+                tricks.push(vec![None; n_players as usize]);
+            }
+            continue;
+        }
+
+        let mut present = Vec::with_capacity(n_players as usize);
+        for _ in 0..n_players {
+            let (new_rest, is_present) = read_present(rest)?;
+            rest = new_rest;
+            present.push(is_present);
+        }
+
+        let n_present = present.iter().filter(|p| **p).count();
+        let mut round_tricks = vec![None; n_players as usize];
+        if n_present > 0 {
+            let (new_rest, value) = read_bits(rest, n_cards as usize + n_present)?;
+            rest = new_rest;
+            let values = deserialize_tricks(value);
+            if values.len() != n_present {
+                return Err(DekuError::Parse(format!("round {} has {} tricks for {} players", round_index + 1, values.len(), n_present)));
+            }
+            let players = present.iter().enumerate().filter(|(_, p)| **p).map(|(player, _)| player);
+            for (player, value) in players.zip(values) {
+                round_tricks[player] = Some(value);
+            }
+        }
+        tricks.push(round_tricks);
+    }
+    Ok((rest, tricks))
+}
+
+fn write_tricks(output: &mut BitVec<u8, Msb0>, version: u8, tricks: &[Vec<Option<u8>>], n_players: u8, schedule: &Schedule, n_rounds: u8) -> Result<(), DekuError> {
+    let cards = round_cards(schedule, n_rounds)?;
+    for (round_tricks, n_cards) in tricks.iter().zip(cards) {
+        let present: Vec<u8> = round_tricks.iter().flatten().copied().collect();
+        let bits = if version < PRESENCE_VERSION {
+            n_cards + n_players
+        } else {
+            for value in round_tricks {
+                write_bits(output, value.is_some() as u32, 1)?;
+            }
+            if present.is_empty() {
+                continue;
+            }
+            n_cards + present.len() as u8
+        };
+
+        let value = serialize_tricks(&present, bits)
+            .ok_or_else(|| DekuError::InvalidParam(format!("tricks {:?} do not fit in {} bits", present, bits)))?;
+        write_bits(output, value, bits as usize)?;
+    }
+    Ok(())
+}
+
+impl Game {
+    pub(crate) fn from_bytes(data: &[u8], version: u8) -> Result<Self, SerializeError> {
+        let (_, game) = Game::read(BitSlice::from_slice(data), version)?;
+        Ok(game)
+    }
+
+    pub(crate) fn to_bytes(&self, version: u8) -> Result<Vec<u8>, SerializeError> {
+        let mut output = BitVec::new();
+        self.write(&mut output, version)?;
+        Ok(output.into_vec())
+    }
+}
+
+impl TryFrom<&JsGame> for Game {
+    type Error = SerializeError;
+
+    fn try_from(value: &JsGame) -> Result<Self, Self::Error> {
+        if value.players.is_empty() {
+            return Err(SerializeError::NoPlayers);
+        }
+        if value.players.len() > MAX_PLAYERS {
+            return Err(SerializeError::TooManyPlayers(value.players.len()));
+        }
+        let first_round = value.rounds.first().ok_or(SerializeError::NoRounds)?;
+        if value.rounds.len() > MAX_ROUNDS {
+            return Err(SerializeError::TooManyRounds(value.rounds.len()));
+        }
+        if first_round.dealer_id as usize >= value.players.len() {
+            return Err(SerializeError::InvalidDealer(first_round.dealer_id));
+        }
+
+        let mut players = Vec::new();
+        let n_players = value.players.len() as u8;
+
+        for player in &value.players {
+            let name_bytes = name_bytes(&player.name)?;
+            players.push(Player {
+                name_length: name_bytes.len() as u8,
+                name: name_bytes
+            })
+        }
+
+        let mut current_round = 1;
+
+        let mut cards = Vec::with_capacity(value.rounds.len());
+        let mut trumps: Vec<Trump> = Vec::new();
+        let mut bids = vec![Vec::with_capacity(value.rounds.len()); n_players as usize];
+        let mut tricks = Vec::with_capacity(value.rounds.len());
+
+        for (round_index, round) in value.rounds.iter().enumerate() {
+            if round.n_cards > MAX_CARDS {
+                return Err(SerializeError::TooManyCards(round.n_cards));
+            }
+            cards.push(round.n_cards);
+            trumps.push(Trump::try_from(round.trump)?);
+
+            // only used by versions before presence bits
+            let complete = |values: &[Option<u8>]| values.len() == n_players as usize && values.iter().all(Option::is_some);
+            if complete(&round.bids) {
+                current_round = round_index as u8 + 1;
+            }
+
+            if complete(&round.tricks) {
+                current_round = round_index as u8 + 2;
+            }
+
+            if round.bids.len() > n_players as usize {
+                return Err(SerializeError::BidsOverflow(round_index as u8 + 1));
+            }
+            for (i, player_bids) in bids.iter_mut().enumerate() {
+                let bid = round.bids.get(i).copied().flatten();
+                if bid.is_some_and(|bid| bid > round.n_cards) {
+                    return Err(SerializeError::BidsOverflow(round_index as u8 + 1));
+                }
+                player_bids.push(bid);
+            }
+
+            let mut round_tricks = round.tricks.clone();
+            round_tricks.resize(n_players as usize, None);
+            let present: Vec<u8> = round_tricks.iter().flatten().copied().collect();
+            if round.tricks.len() > n_players as usize || serialize_tricks(&present, round.n_cards + present.len() as u8).is_none() {
+                return Err(SerializeError::TricksOverflow(round_index as u8 + 1));
+            }
+            tricks.push(round_tricks);
+        }
+
+        let n_rounds = value.rounds.len() as u8;
+        let name_bytes = name_bytes(&value.name)?;
+        Ok(Game {
+            name_length: name_bytes.len() as u8,
+            name: name_bytes,
+            n_players,
+            players,
+            start_dealer: first_round.dealer_id,
+            n_rounds,
+            current_round,
+            schedule: Schedule::from_cards(&cards),
+            scoring: value.scoring,
+            trumps,
+            bids,
+            tricks
+        })
+    }
+}
+
+impl TryFrom<Game> for JsGame {
+    type Error = SerializeError;
+
+    fn try_from(value: Game) -> Result<Self, Self::Error> {
+        if value.n_players == 0 {
+            return Err(SerializeError::NoPlayers);
+        }
+        if value.start_dealer >= value.n_players {
+            return Err(SerializeError::InvalidDealer(value.start_dealer));
+        }
+
+        let mut players = Vec::new();
+        for (id, player) in value.players.into_iter().enumerate() {
+            let name = String::from_utf8(player.name)?;
+            players.push(JsPlayer {
+                id: id as u8,
+                name
+            });
+        }
+
+        let cards = round_cards(&value.schedule, value.n_rounds)?;
+        // a round without any values has empty bids or tricks
+        let entered = |values: Vec<Option<u8>>| if values.iter().all(Option::is_none) { Vec::new() } else { values };
+
+        let mut rounds = Vec::new();
+        let mut dealer_id = value.start_dealer;
+        for (round_index, trump) in value.trumps.iter().enumerate() {
+            let bids = value.bids.iter().map(|player_bids| player_bids[round_index]).collect();
+
+            rounds.push(JsRound {
+                n_cards: cards[round_index],
+                trump: trump.clone() as u8,
+                bids: entered(bids),
+                tricks: entered(value.tricks[round_index].clone()),
+                dealer_id
+            });
+
+            dealer_id = (dealer_id + 1) % value.n_players;
+        }
+
+        Ok(JsGame {
+            game_version: 3,
+            name: String::from_utf8(value.name)?,
+            scoring: value.scoring,
+            players,
+            rounds
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compact::Game;
+    use crate::schedule::Schedule;
+    use crate::score::Scoring;
+    use crate::{crc16, decode, encode, JsGame, JsPlayer, JsRound, SerializeError, FORMAT_VERSION, MAGIC};
+
+    // every round has bids and tricks, with the tricks skewed towards the last player
+    fn full_game(n_players: u8) -> JsGame {
+        game_with_schedule(n_players, &Schedule::default())
+    }
+
+    fn game_with_schedule(n_players: u8, schedule: &Schedule) -> JsGame {
+        let rounds = schedule.cards().iter().enumerate().map(|(round_index, &n_cards)| {
+            let mut tricks = vec![0; n_players as usize];
+            let mut remaining = n_cards;
+            for (player, t) in tricks.iter_mut().enumerate() {
+                *t = remaining.min(player as u8);
+                remaining -= *t;
+            }
+            tricks[n_players as usize - 1] += remaining;
+
+            JsRound {
+                n_cards,
+                trump: (round_index % 5) as u8,
+                bids: (0..n_players).map(|player| Some((player + round_index as u8) % (n_cards + 1))).collect(),
+                tricks: tricks.into_iter().map(Some).collect(),
+                dealer_id: (round_index % n_players as usize) as u8,
+            }
+        }).collect();
+
+        JsGame {
+            game_version: 3,
+            name: format!("{} players", n_players),
+            scoring: Scoring::Standard,
+            players: (0..n_players).map(|id| JsPlayer { id, name: format!("Player {}", id + 1) }).collect(),
+            rounds,
+        }
+    }
+
+    #[test]
+    fn test_round_trip_all_player_counts() {
+        for n_players in 1..=7 {
+            let game = full_game(n_players);
+            let decoded = decode(&encode(&game).unwrap()).unwrap();
+            assert_eq!(game, decoded, "{} players", n_players);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_all_tricks_to_one_player() {
+        for n_players in 1..=7 {
+            let mut game = full_game(n_players);
+            for round in game.rounds.iter_mut() {
+                round.tricks = vec![Some(0); n_players as usize];
+                round.tricks[0] = Some(round.n_cards);
+            }
+            let decoded = decode(&encode(&game).unwrap()).unwrap();
+            assert_eq!(game, decoded, "{} players", n_players);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_schedules() {
+        let schedules = [
+            Schedule::HeenEnTerug(7),
+            Schedule::Heen(10),
+            Schedule::HeenEnTerug(13),
+            Schedule::Custom(vec![1, 2, 3, 4, 5, 5, 5, 4, 3, 2, 1, 0]),
+        ];
+        for schedule in schedules.iter() {
+            for n_players in 1..=7 {
+                let game = game_with_schedule(n_players, schedule);
+                let decoded = decode(&encode(&game).unwrap()).unwrap();
+                assert_eq!(game, decoded, "{:?} with {} players", schedule, n_players);
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip_partial_bids() {
+        let mut game = full_game(5);
+        game.rounds[3].tricks.clear();
+        game.rounds[4].bids = vec![Some(2), None, Some(0)];
+        game.rounds[4].tricks.clear();
+        for round in game.rounds[5..].iter_mut() {
+            round.bids.clear();
+            round.tricks.clear();
+        }
+
+        let decoded = decode(&encode(&game).unwrap()).unwrap();
+        assert_eq!(vec![Some(2), None, Some(0), None, None], decoded.rounds[4].bids);
+        game.rounds[4].bids.resize(5, None);
+        assert_eq!(game, decoded);
+    }
+
+    #[test]
+    fn test_round_trip_partial_tricks() {
+        let mut game = full_game(4);
+        game.rounds[0].tricks = vec![None, Some(10), None, Some(0)];
+        game.rounds[1].tricks = vec![Some(9), None, None, None];
+        game.rounds[2].bids = vec![None, None, None, Some(8)];
+        game.rounds[2].tricks.clear();
+
+        let decoded = decode(&encode(&game).unwrap()).unwrap();
+        assert_eq!(game, decoded);
+    }
+
+    #[test]
+    fn test_round_trip_scoring() {
+        for scoring in [Scoring::Standard, Scoring::MissPenalty, Scoring::TenPlusBid, Scoring::ZeroBidBonus] {
+            let mut game = full_game(4);
+            game.scoring = scoring;
+            assert_eq!(game, decode(&encode(&game).unwrap()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_version_5_uses_standard_scoring() {
+        let mut game = full_game(4);
+        game.scoring = Scoring::TenPlusBid;
+        let body = Game::try_from(&game).unwrap().to_bytes(5).unwrap();
+        let decoded = Game::from_bytes(&body, 5).unwrap();
+        assert_eq!(Scoring::Standard, decoded.scoring);
+    }
+
+    #[test]
+    fn test_decode_version_4() {
+        let mut game = full_game(3);
+        game.rounds[5].tricks.clear();
+        game.rounds[6].bids = vec![Some(1), None, None];
+        game.rounds[6].tricks.clear();
+        for round in game.rounds[7..].iter_mut() {
+            round.bids.clear();
+            round.tricks.clear();
+        }
+
+        let body = Game::try_from(&game).unwrap().to_bytes(4).unwrap();
+        let encoded = [&MAGIC[..], &[4], &crc16(&body).to_be_bytes(), &body].concat();
+
+        // before presence bits only complete rounds were stored
+        game.rounds[6].bids.clear();
+        assert_eq!(game, decode(&encoded).unwrap());
+    }
+
+    #[test]
+    fn test_decode_rejects_dealer_who_is_not_a_player() {
+        let mut compact = Game::try_from(&full_game(3)).unwrap();
+        compact.start_dealer = 6;
+        let body = compact.to_bytes(FORMAT_VERSION).unwrap();
+        let encoded = [&MAGIC[..], &[FORMAT_VERSION], &crc16(&body).to_be_bytes(), &body].concat();
+        assert_eq!(Err(SerializeError::InvalidDealer(6)), decode(&encoded));
+    }
+
+    #[test]
+    fn test_decode_version_2() {
+        let game = full_game(6);
+        let body = Game::try_from(&game).unwrap().to_bytes(2).unwrap();
+        let encoded = [&MAGIC[..], &[2], &body].concat();
+        assert_eq!(game, decode(&encoded).unwrap());
+    }
+
+    #[test]
+    fn test_version_2_stores_no_schedule() {
+        let game = game_with_schedule(4, &Schedule::Heen(10));
+        let body = Game::try_from(&game).unwrap().to_bytes(2).unwrap();
+        let decoded = Game::from_bytes(&body, 2).unwrap();
+        assert_eq!(Schedule::default(), decoded.schedule);
+    }
+}
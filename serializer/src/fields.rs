@@ -0,0 +1,63 @@
+// Fields every layout stores the same way.
+
+use deku::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::SerializeError;
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite)]
+#[deku(type= "u8", bits = "3")]
+pub(crate) enum Trump {
+    #[deku(id = "0b00")]
+    Spade,
+    #[deku(id = "0b01")]
+    Heart,
+    #[deku(id = "0b10")]
+    Club,
+    #[deku(id = "0b11")]
+    Diamond,
+    #[deku(id = "0b100")]
+    None
+}
+
+impl TryFrom<u8> for Trump {
+    type Error = SerializeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Trump::Spade),
+            1 => Ok(Trump::Heart),
+            2 => Ok(Trump::Club),
+            3 => Ok(Trump::Diamond),
+            4 => Ok(Trump::None),
+            _ => Err(SerializeError::InvalidTrump(value))
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite)]
+pub(crate) struct Player {
+    #[deku(update = "self.name.len()")]
+    pub(crate) name_length: u8,
+    #[deku(count = "name_length")]
+    pub(crate) name: Vec<u8>,
+}
+
+/// The rules a game is scored with. Stored in encoded games, so ids must not be reused.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, DekuRead, DekuWrite)]
+#[serde(rename_all = "camelCase")]
+#[deku(type = "u8", bits = "3")]
+pub enum Scoring {
+    /// 5 + bid on a hit, the tricks taken on a miss.
+    #[default]
+    #[deku(id = "0")]
+    Standard,
+    /// 5 + bid on a hit, minus the difference between bid and tricks on a miss.
+    #[deku(id = "1")]
+    MissPenalty,
+    /// 10 + bid on a hit, nothing on a miss.
+    #[deku(id = "2")]
+    TenPlusBid,
+    /// Like `Standard`, but a hit on a zero bid scores 5 + the number of cards in the round.
+    #[deku(id = "3")]
+    ZeroBidBonus,
+}
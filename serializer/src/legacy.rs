@@ -0,0 +1,455 @@
+// The layout used before the format header existed, and for format version 1.
+// Bid and trick bit fields are sized per round index for the 10 -> 1 -> 10 schedule and 5 players.
+
+use deku::prelude::*;
+use paste::paste;
+use crate::score::Scoring;
+use crate::{deserialize_tricks, name_bytes, serialize_tricks, JsGame, JsPlayer, JsRound, Player, SerializeError, Trump};
+
+const MAX_PLAYERS: usize = 5;
+const MAX_ROUNDS: usize = 19;
+// card counts are not stored, rounds always follow this schedule
+const CARDS_PER_ROUND: [u8; MAX_ROUNDS] = [10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+
+impl TryFrom<JsGame> for Game {
+    type Error = SerializeError;
+
+    fn try_from(value: JsGame) -> Result<Self, Self::Error> {
+        if value.players.is_empty() {
+            return Err(SerializeError::NoPlayers);
+        }
+        if value.players.len() > MAX_PLAYERS {
+            return Err(SerializeError::TooManyPlayers(value.players.len()));
+        }
+        let first_round = value.rounds.first().ok_or(SerializeError::NoRounds)?;
+        if value.rounds.len() > MAX_ROUNDS {
+            return Err(SerializeError::TooManyRounds(value.rounds.len()));
+        }
+
+        let mut players = Vec::new();
+        let n_players = value.players.len() as u8;
+
+        for player in &value.players {
+            let name_bytes = name_bytes(&player.name)?;
+            players.push(Player {
+                name_length: name_bytes.len() as u8,
+                name: name_bytes
+            })
+        }
+
+        let mut current_round = 1;
+
+        let mut trumps: Vec<Trump> = Vec::new();
+        let mut player_bids: Vec<PlayerScore> = Vec::with_capacity(MAX_PLAYERS);
+        for _ in 0..n_players {
+            player_bids.push(PlayerScore::default());
+        }
+
+        let mut tricks = AllTricks::default();
+
+        for (round_index, round) in value.rounds.iter().enumerate() {
+            trumps.push(Trump::try_from(round.trump)?);
+
+            // this layout can only store complete rounds
+            let complete = |values: &[Option<u8>]| values.len() == n_players as usize && values.iter().all(Option::is_some);
+            if complete(&round.bids) {
+                current_round = round_index as u8 + 1;
+            }
+
+            if complete(&round.tricks) {
+                current_round = round_index as u8 + 2;
+            }
+
+            for i in 0..n_players {
+                let bids = player_bids.get_mut(i as usize).unwrap();
+                bids.set_round(round_index as u8 + 1, round.bids.get(i as usize).copied().flatten().unwrap_or(0));
+            }
+
+            let round_tricks: Vec<u8> = round.tricks.iter().flatten().copied().collect();
+            let bits = CARDS_PER_ROUND[round_index] + MAX_PLAYERS as u8;
+            tricks[(round_index as u8) + 1] = serialize_tricks(&round_tricks, bits)
+                .ok_or(SerializeError::TricksOverflow(round_index as u8 + 1))? as u16;
+
+        }
+
+        let n_rounds = value.rounds.len() as u8;
+        let name_bytes = name_bytes(&value.name)?;
+        Ok(Game {
+            name_length: name_bytes.len() as u8,
+            name: name_bytes,
+            n_players,
+            players,
+            start_dealer: first_round.dealer_id,
+            n_rounds,
+            current_round,
+            trumps,
+            player_bids,
+            tricks
+        })
+    }
+}
+
+impl TryFrom<Game> for JsGame {
+    type Error = SerializeError;
+
+    fn try_from(value: Game) -> Result<Self, Self::Error> {
+        if value.n_players == 0 {
+            return Err(SerializeError::NoPlayers);
+        }
+        if value.n_players as usize > MAX_PLAYERS {
+            return Err(SerializeError::TooManyPlayers(value.n_players as usize));
+        }
+        if value.n_rounds as usize > MAX_ROUNDS {
+            return Err(SerializeError::TooManyRounds(value.n_rounds as usize));
+        }
+
+        let mut players = Vec::new();
+        for (id, player) in value.players.into_iter().enumerate() {
+            let name = String::from_utf8(player.name)?;
+            players.push(JsPlayer {
+                id: id as u8,
+                name
+            });
+        }
+
+        let mut rounds = Vec::new();
+        let mut dealer_id = value.start_dealer;
+        for (round_index, trump) in value.trumps.iter().enumerate() {
+            let mut bids = Vec::new();
+            let mut tricks = Vec::new();
+            for player in 0..value.n_players {
+                if round_index < value.current_round as usize {
+                    bids.push(Some(value.player_bids[player as usize].get_round(round_index as u8 + 1)));
+                }
+
+            }
+
+            if round_index + 1 < value.current_round as usize {
+                tricks = deserialize_tricks(value.tricks[round_index as u8 + 1] as u32).into_iter().map(Some).collect();
+            }
+
+            rounds.push(JsRound {
+                n_cards: CARDS_PER_ROUND[round_index],
+                trump: trump.clone() as u8,
+                bids,
+                tricks,
+                dealer_id
+            });
+
+            dealer_id = (dealer_id + 1) % value.n_players;
+        }
+
+        Ok(JsGame {
+            game_version: 3,
+            name: String::from_utf8(value.name)?,
+            scoring: Scoring::Standard,
+            players,
+            rounds
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, DekuRead, DekuWrite)]
+pub(crate) struct Game {
+    name_length: u8,
+    #[deku(count = "name_length")]
+    name: Vec<u8>,
+    #[deku(bits = "3")]
+    n_players: u8,
+    #[deku(count = "n_players")]
+    players: Vec<Player>,
+    #[deku(bits = "3")]
+    start_dealer: u8,
+    #[deku(bits="5")]
+    n_rounds: u8, // should be 19
+    #[deku(bits = "5")]
+    current_round: u8,
+    #[deku(count = "n_rounds")]
+    trumps: Vec<Trump>,
+    #[deku(count = "n_players")]
+    player_bids: Vec<PlayerScore>,
+    tricks: AllTricks
+
+}
+
+macro_rules! zero_nonzero {
+    ($round:tt, $bits:tt) => {
+        use deku::prelude::*;
+
+    paste::paste! {
+        #[derive(Debug, Clone, Default, PartialEq, DekuRead, DekuWrite)]
+        #[deku(type = "u8", bits = "1")]
+        pub enum [<Value$round>] {
+            #[default]
+            #[deku(id = "0")]
+            Zero,
+            #[deku(id = "1")]
+            NonZero(#[deku(bits = $bits)] u8)
+        }
+        impl From<u8> for [<Value$round>] {
+            fn from(value: u8) -> Self {
+                match value {
+                    0 => [<Value$round>]::Zero,
+                    _ => [<Value$round>]::NonZero(value - 1)
+                }
+            }
+        }
+
+        impl From<[<Value$round>]> for u8 {
+            fn from(value: [<Value$round>]) -> Self {
+                match value {
+                    [<Value$round>]::Zero => 0,
+                    [<Value$round>]::NonZero(v) => v + 1
+                }
+            }
+        }
+    }
+    }
+}
+
+macro_rules! max_three_or_higher {
+    ($round:tt, $bits:tt) => {
+        use deku::prelude::*;
+
+    paste::paste! {
+        #[derive(Debug, Clone, PartialEq, DekuRead, DekuWrite)]
+        #[deku(type = "u8", bits = "1")]
+        pub enum [<Value$round>] {
+            #[deku(id = "0")]
+            MaxThree(#[deku(bits = "2")] u8),
+            #[deku(id = "1")]
+            Higher(#[deku(bits = $bits)] u8)
+        }
+
+        impl Default for [<Value$round>] {
+            fn default() -> Self {
+                [<Value$round>]::MaxThree(0)
+            }
+        }
+
+        impl From<u8> for [<Value$round>] {
+            fn from(value: u8) -> Self {
+                match value {
+                    0..=3 => [<Value$round>]::MaxThree(value),
+                    _ => [<Value$round>]::Higher(value - 3)
+                }
+            }
+        }
+        impl From<[<Value$round>]> for u8 {
+            fn from(value: [<Value$round>]) -> Self {
+                match value {
+                    [<Value$round>]::MaxThree(v) => v,
+                    [<Value$round>]::Higher(v) => v + 3
+                }
+            }
+        }
+    }
+    }
+}
+
+macro_rules! tailored_enum {
+    (1) => { // 10
+        max_three_or_higher!(1, 3);
+    };
+    (2) => { // 9
+        max_three_or_higher!(2, 3);
+    };
+    (3) => { // 8
+        max_three_or_higher!(3, 3);
+    };
+    (4) => { // 7
+        max_three_or_higher!(4, 3);
+    };
+    (5) => { // 6
+        max_three_or_higher!(5, 2);
+    };
+    (6) => { // 5
+        zero_nonzero!(6, 3);
+    };
+    (7) => { // 4
+        zero_nonzero!(7, 2);
+    };
+    (8) => { // 3
+        zero_nonzero!(8, 2);
+    };
+    (9) => { // 2
+        zero_nonzero!(9, 1);
+    };
+    (10) => { // 1
+        zero_nonzero!(10, 1);
+    };
+    (11) => { // 2
+        zero_nonzero!(11, 1);
+    };
+    (12) => { // 3
+        zero_nonzero!(12, 2);
+    };
+    (13) => { // 4
+        zero_nonzero!(13, 2);
+    };
+    (14) => { // 5
+        zero_nonzero!(14, 3);
+    };
+    (15) => { // 6
+        max_three_or_higher!(15, 2);
+    };
+    (16) => { // 7
+        max_three_or_higher!(16, 3);
+    };
+    (17) => { // 8
+        max_three_or_higher!(17, 3);
+    };
+    (18) => { // 9
+        max_three_or_higher!(18, 3);
+    };
+    (19) => { // 10
+        max_three_or_higher!(19, 3);
+    };
+}
+
+macro_rules! player_score {
+    ($name:ident [ $($round_id:tt),* ]) => {
+        paste! {
+            use deku::prelude::*;
+            use std::ops::{Index, IndexMut};
+
+            mod [<values_$name:lower>] {
+                $(
+                tailored_enum!($round_id);
+                )*
+            }
+
+            #[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
+            pub struct $name {
+            $(
+            pub [<round_$round_id>]: [<values_$name:lower>]::[<Value$round_id>],
+            )*
+            }
+
+            impl $name {
+                pub fn get_round(&self, index: u8) -> u8 {
+                    let result: u8 = match index {
+                        $( $round_id => self.[<round_$round_id>].clone().into(), )*
+                        _ => panic!("Invalid index: {}", index)
+                    };
+                    result
+                }
+
+                pub fn set_round(&mut self, index: u8, value: u8) {
+                    match index {
+                        $( $round_id => self.[<round_$round_id>] = value.into(), )*
+                        _ => panic!("Invalid index: {}", index)
+                    }
+                }
+            }
+        }
+    }
+}
+
+player_score!(PlayerScore
+    [1, 2, 3, 4, 5, 6, 7, 8, 9, 10,
+     11, 12, 13, 14, 15, 16, 17, 18, 19]);
+
+
+macro_rules! variable_bit_array {
+    ($name:ident $type:ident [ $(($round_id:tt, $bits:tt)),* ]) => {
+        paste! {
+            use deku::prelude::*;
+
+            #[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
+            #[deku(endian="big")]
+            pub struct $name {
+            $(
+            #[deku(bits = $bits)]
+            pub [<round_$round_id>]: $type,
+            )*
+            }
+
+            impl std::ops::Index<u8> for $name {
+                type Output = $type;
+                fn index(&self, index: u8) -> &Self::Output {
+                    match index {
+                        $( $round_id => &self.[<round_$round_id>], )*
+                        _ => panic!("Invalid index: {}", index)
+                    }
+                }
+            }
+
+            impl std::ops::IndexMut<u8> for $name {
+                fn index_mut(&mut self, index: u8) -> &mut Self::Output {
+                    match index {
+                        $( $round_id => &mut self.[<round_$round_id>], )*
+                        _ => panic!("Invalid index: {}", index)
+                    }
+                }
+            }
+        }
+    }
+}
+
+// for 5 players
+variable_bit_array!(AllTricks u16
+    [(1, 15), (2, 14), (3, 13), (4, 12), (5, 11), (6, 10),
+        (7, 9), (8, 8), (9, 7), (10, 6), (11, 7), (12, 8), (13, 9),
+        (14, 10), (15, 11), (16, 12), (17, 13), (18, 14), (19, 15)]);
+
+
+#[cfg(test)]
+mod test {
+    use deku::{DekuContainerRead, DekuContainerWrite};
+    use crate::{deserialize_tricks, serialize_tricks, JsGame, JsPlayer, JsRound};
+    use crate::legacy::{AllTricks, Game};
+    use crate::score::Scoring;
+    use crate::legacy::values_playerscore::Value1;
+    use paste::paste;
+
+    #[test]
+    fn test_8bit_tricks_serialize() {
+        let tricks = vec![0, 0, 1, 2, 0];
+        let serialized = serialize_tricks(&tricks, 8).unwrap();
+        assert_eq!(0b11101001, serialized);
+
+        let container = AllTricks { round_8: serialized as u16, ..Default::default() };
+        let container_serialized = container.to_bytes().unwrap();
+        let (_, container_deserialized) = AllTricks::from_bytes((&container_serialized, 0)).unwrap();
+
+        let original = deserialize_tricks(container_deserialized.round_8 as u32);
+        assert_eq!(tricks, original);
+    }
+
+    #[test]
+    fn test_variable_bit_array() {
+        player_score!(TestStruct [1, 2]);
+        let _bids = TestStruct::default();
+    }
+
+    #[test]
+    fn test_serialize_round_scores() {
+        let js_game = JsGame {
+            game_version: 3,
+            name: "Testgame".to_string(),
+            scoring: Scoring::Standard,
+            players: vec![
+                JsPlayer {
+                    id: 0,
+                    name: "Player 1".to_string()
+                },
+                JsPlayer {
+                    id: 1,
+                    name: "Player 2".to_string()
+                },
+            ],
+            rounds: vec![ JsRound {
+                n_cards: 10,
+                trump: 1,
+                bids: vec![Some(2), Some(3)],
+                tricks: vec![Some(4), Some(6)],
+                dealer_id: 0
+            }]
+        };
+
+        let game = Game::try_from(js_game).unwrap();
+        assert_eq!(game.player_bids[0].round_1, Value1::MaxThree(2));
+        assert_eq!(game.player_bids[1].round_1, Value1::MaxThree(3));
+    }
+}
@@ -8,30 +8,129 @@ use deku::prelude::*;
 use paste::paste;
 use serde::{Serialize, Deserialize};
 use wasm_bindgen::prelude::*;
-
-
-#[derive(Debug)]
+use crate::score::{score_game, Scoring};
+use crate::fields::{Player, Trump};
+use crate::validate::validate_game;
+
+mod compact;
+// DekuRead on a type without a ctx also derives DekuContainerRead, which rounds bits up to bytes
+// with (bits + 7) / 8. Only these modules have such top level types.
+#[allow(clippy::manual_div_ceil)]
+mod fields;
+#[allow(clippy::manual_div_ceil)]
+mod legacy;
+pub mod payout;
+mod schedule;
+pub mod score;
+pub mod share;
+pub mod validate;
+
+// Encoded games start with MAGIC followed by a format version byte. Payloads from before the
+// header existed start directly with the game name length; a name of 255 bytes starting with 'H'
+// would be needed to mistake one for a header.
+const MAGIC: [u8; 2] = [0xFF, b'H'];
+const LEGACY_VERSION: u8 = 1;
+// The first version with the header, in the compact format.
+const COMPACT_VERSION: u8 = 2;
+// From this version on the header ends with a CRC-16 of the body.
+const CHECKSUM_VERSION: u8 = 4;
+const FORMAT_VERSION: u8 = 6;
+
+// n_players is a 3 bit field
+const MAX_PLAYERS: usize = 7;
+// name lengths are stored in a byte
+const MAX_NAME_BYTES: usize = u8::MAX as usize;
+// n_rounds is a 5 bit field. Versions before 5 can only store 30 rounds, current_round points one
+// past the last finished round.
+const MAX_ROUNDS: usize = 31;
+
+#[derive(Debug, PartialEq)]
 pub enum SerializeError {
-    Error(String),
+    /// The data ended before the game was complete.
+    Truncated,
+    /// The data could not be parsed as a game.
+    Corrupted(String),
+    /// The data does not match its checksum, it was cut off or altered.
+    ChecksumMismatch,
+    UnsupportedVersion(u8),
+    InvalidTrump(u8),
+    /// The game name or a player name is not valid UTF-8.
+    InvalidName,
+    NameTooLong(usize),
+    NoPlayers,
+    TooManyPlayers(usize),
+    NoRounds,
+    TooManyRounds(usize),
+    TooManyCards(u8),
+    /// The dealer of the first round is not one of the players.
+    InvalidDealer(u8),
+    /// The bids of the given round (1-based) do not fit in the format.
+    BidsOverflow(u8),
+    /// The tricks of the given round (1-based) do not fit in the format.
+    TricksOverflow(u8),
+    /// Converting from or to a JavaScript value failed.
+    Js(String),
+    Json(String),
+    Base64(String),
 }
 
 impl Display for SerializeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            SerializeError::Error(msg) => write!(f, "{}", msg)
+            SerializeError::Truncated => write!(f, "Game data is truncated"),
+            SerializeError::Corrupted(msg) => write!(f, "Game data is corrupted: {}", msg),
+            SerializeError::ChecksumMismatch => write!(f, "Game data is corrupted: checksum does not match"),
+            SerializeError::UnsupportedVersion(version) => write!(f, "Unsupported game format version: {}", version),
+            SerializeError::InvalidTrump(trump) => write!(f, "Invalid trump: {}", trump),
+            SerializeError::InvalidName => write!(f, "Name is not valid UTF-8"),
+            SerializeError::NameTooLong(length) => write!(f, "Name is too long: {} bytes", length),
+            SerializeError::NoPlayers => write!(f, "Game has no players"),
+            SerializeError::TooManyPlayers(n) => write!(f, "Too many players: {}", n),
+            SerializeError::NoRounds => write!(f, "Game has no rounds"),
+            SerializeError::TooManyRounds(n) => write!(f, "Too many rounds: {}", n),
+            SerializeError::TooManyCards(n) => write!(f, "Too many cards: {}", n),
+            SerializeError::InvalidDealer(dealer) => write!(f, "Dealer {} is not a player", dealer),
+            SerializeError::BidsOverflow(round) => write!(f, "Bids of round {} do not fit", round),
+            SerializeError::TricksOverflow(round) => write!(f, "Tricks of round {} do not fit", round),
+            SerializeError::Js(msg) => write!(f, "{}", msg),
+            SerializeError::Json(msg) => write!(f, "Invalid JSON: {}", msg),
+            SerializeError::Base64(msg) => write!(f, "Invalid Base64: {}", msg),
         }
     }
 }
 
-impl <E>From<E> for SerializeError where E: std::error::Error {
-    fn from(e: E) -> Self {
-        SerializeError::Error(format!("{:?}", e))
+impl std::error::Error for SerializeError {}
+
+impl From<DekuError> for SerializeError {
+    fn from(e: DekuError) -> Self {
+        match e {
+            DekuError::Incomplete(_) => SerializeError::Truncated,
+            e => SerializeError::Corrupted(e.to_string()),
+        }
+    }
+}
+
+impl From<serde_wasm_bindgen::Error> for SerializeError {
+    fn from(e: serde_wasm_bindgen::Error) -> Self {
+        SerializeError::Js(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for SerializeError {
+    fn from(e: serde_json::Error) -> Self {
+        SerializeError::Json(e.to_string())
+    }
+}
+
+impl From<std::string::FromUtf8Error> for SerializeError {
+    fn from(_: std::string::FromUtf8Error) -> Self {
+        SerializeError::InvalidName
     }
 }
 
-impl Into<JsValue> for SerializeError {
-    fn into(self) -> JsValue {
-        JsValue::from_str(&self.to_string())
+impl From<SerializeError> for JsValue {
+    fn from(e: SerializeError) -> Self {
+        JsValue::from_str(&e.to_string())
     }
 }
 
@@ -50,32 +149,95 @@ extern "C" {
 pub fn serialize(val: JsValue) -> Result<Vec<u8>, SerializeError> {
     let result  = JsGame::from_js_value(val);
     match result {
-        Ok(js_game) => {
-            let game = Game::from(js_game);
-            let serialized: Vec<u8> = game.try_into().unwrap();
-            return Ok(serialized);
-        },
+        Ok(js_game) => encode(&js_game),
         Err(e) => {
             error(&format!("{}", e));
-            return Err(e);
+            Err(e)
         }
     }
 }
 
 #[wasm_bindgen]
 pub fn deserialize(data: Vec<u8>) -> Result<JsValue, SerializeError> {
-    let (_, game) = Game::from_bytes((&data, 0)).unwrap();
-
-    let js_game: JsGame = game.into();
+    let js_game = decode(&data)?;
     let js_value = js_game.to_js_value()?;
     Ok(js_value)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[wasm_bindgen]
+pub fn validate(val: JsValue) -> Result<JsValue, SerializeError> {
+    let js_game = JsGame::from_js_value(val)?;
+    let violations = validate_game(&js_game);
+    Ok(serde_wasm_bindgen::to_value(&violations)?)
+}
+
+#[wasm_bindgen]
+pub fn score(val: JsValue) -> Result<JsValue, SerializeError> {
+    let js_game = JsGame::from_js_value(val)?;
+    Ok(serde_wasm_bindgen::to_value(&score_game(&js_game))?)
+}
+
+/// Encode a game in the latest format version.
+pub fn encode(js_game: &JsGame) -> Result<Vec<u8>, SerializeError> {
+    let game = compact::Game::try_from(js_game)?;
+    let body = game.to_bytes(FORMAT_VERSION)?;
+
+    let mut result = Vec::with_capacity(MAGIC.len() + 3 + body.len());
+    result.extend_from_slice(&MAGIC);
+    result.push(FORMAT_VERSION);
+    result.extend_from_slice(&crc16(&body).to_be_bytes());
+    result.extend(body);
+    Ok(result)
+}
+
+/// Decode a game encoded in any known format version.
+pub fn decode(data: &[u8]) -> Result<JsGame, SerializeError> {
+    let (version, body) = match data.strip_prefix(&MAGIC) {
+        Some([version, body @ ..]) => (*version, body),
+        _ => (LEGACY_VERSION, data),
+    };
+
+    match version {
+        LEGACY_VERSION => {
+            let (_, game) = legacy::Game::from_bytes((body, 0))?;
+            game.try_into()
+        }
+        COMPACT_VERSION..CHECKSUM_VERSION => compact::Game::from_bytes(body, version)?.try_into(),
+        CHECKSUM_VERSION..=FORMAT_VERSION => compact::Game::from_bytes(verify_checksum(body)?, version)?.try_into(),
+        _ => Err(SerializeError::UnsupportedVersion(version)),
+    }
+}
+
+fn verify_checksum(data: &[u8]) -> Result<&[u8], SerializeError> {
+    let [high, low, body @ ..] = data else {
+        return Err(SerializeError::Truncated);
+    };
+    if u16::from_be_bytes([*high, *low]) != crc16(body) {
+        return Err(SerializeError::ChecksumMismatch);
+    }
+    Ok(body)
+}
+
+// CRC-16/CCITT-FALSE
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JsGame {
     pub game_version: u8,
     pub name: String,
+    /// Games from before scoring variants use the standard rules.
+    #[serde(default)]
+    pub scoring: Scoring,
     pub players: Vec<JsPlayer>,
     pub rounds: Vec<JsRound>,
 }
@@ -96,196 +258,44 @@ impl JsGame {
             Err(e) => Err(SerializeError::from(e))
         }
     }
-}
-
-impl From<JsGame> for Game {
-    fn from(value: JsGame) -> Self {
-        let mut players = Vec::new();
-        let n_players = value.players.len() as u8;
-
-        for player in &value.players {
-            let name_bytes = player.name.clone().into_bytes();
-            players.push(Player {
-                name_length: name_bytes.len() as u8,
-                name: name_bytes
-            })
-        }
-
-        let mut current_round = 1;
-
-        let mut trumps: Vec<Trump> = Vec::new();
-        let mut player_bids: Vec<PlayerScore> = Vec::with_capacity(5);
-        for _ in 0..n_players {
-            player_bids.push(PlayerScore::default());
-        }
-
-        let mut tricks = AllTricks::default();
 
-        for (round_index, round) in value.rounds.iter().enumerate() {
-            trumps.push(round.trump.into());
-
-            if round.bids.len() == n_players as usize {
-                current_round = round_index as u8 + 1;
-            }
-
-            if round.tricks.len() == n_players as usize {
-                current_round = round_index as u8 + 2;
-            }
-
-            for i in 0..n_players {
-                let bids = player_bids.get_mut(i as usize).unwrap();
-                bids.set_round(round_index as u8 + 1, *round.bids.get(i as usize).unwrap_or(&0u8));
-            }
-
-            tricks[(round_index as u8) + 1] = serialize_tricks(&round.tricks);
-
-        }
+    /// Read a game as the app stores it, fields the serializer does not use are ignored.
+    pub fn from_json(json: &str) -> Result<Self, SerializeError> {
+        Ok(serde_json::from_str(json)?)
+    }
 
-        let n_rounds = value.rounds.len() as u8;
-        let name_bytes = value.name.clone().into_bytes();
-        Game {
-            name_length: name_bytes.len() as u8,
-            name: name_bytes,
-            n_players,
-            players,
-            start_dealer: value.rounds[0].dealer_id,
-            n_rounds,
-            current_round,
-            trumps,
-            player_bids,
-            tricks
-        }
-    } 
+    pub fn to_json(&self) -> Result<String, SerializeError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
 }
 
-impl From<Game> for JsGame {
-    fn from(value: Game) -> Self {
-        let mut players = Vec::new();
-        let mut id = 0;
-        for player in value.players {
-            let name = String::from_utf8(player.name).unwrap();
-            players.push(JsPlayer {
-                id,
-                name
-            });
-
-            id+=1;
-        }
-
-        let mut rounds = Vec::new();
-        let mut dealer_id = value.start_dealer;
-        let cards_per_round = [10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
-        for (round_index, trump) in value.trumps.iter().enumerate() {
-            let mut bids = Vec::new();
-            let mut tricks = Vec::new();
-            for player in 0..value.n_players {
-                if round_index + 1 <= value.current_round as usize {
-                    bids.push(value.player_bids[player as usize].get_round(round_index as u8 + 1));
-                }
-
-            }
-
-            if round_index + 1 < value.current_round as usize {
-                tricks = deserialize_tricks(&value.tricks[round_index as u8 + 1]);
-            }
-
-            rounds.push(JsRound {
-                n_cards: cards_per_round[round_index],
-                trump: trump.clone() as u8,
-                bids,
-                tricks,
-                dealer_id
-            });
-
-            dealer_id = (dealer_id + 1) % value.n_players;
-        }
-
-        JsGame {
-            game_version: 3,
-            name: String::from_utf8(value.name).unwrap(),
-            players,
-            rounds
-        }
+fn name_bytes(name: &str) -> Result<Vec<u8>, SerializeError> {
+    let bytes = name.as_bytes().to_vec();
+    if bytes.len() > MAX_NAME_BYTES {
+        return Err(SerializeError::NameTooLong(bytes.len()));
     }
+    Ok(bytes)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JsPlayer {
     pub id: u8,
     pub name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JsRound {
     pub n_cards: u8,
     pub trump: u8,
-    pub bids: Vec<u8>,
-    pub tricks: Vec<u8>,
+    /// One entry per player, `None` for a player that has not bid yet. Empty until somebody bids.
+    pub bids: Vec<Option<u8>>,
+    /// One entry per player, `None` for a player whose tricks have not been entered yet.
+    pub tricks: Vec<Option<u8>>,
     pub dealer_id: u8
 }
 
-#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite)]
-#[deku(type= "u8", bits = "3")]
-enum Trump {
-    #[deku(id = "0b00")]
-    Spade,
-    #[deku(id = "0b01")]
-    Heart,
-    #[deku(id = "0b10")]
-    Club,
-    #[deku(id = "0b11")]
-    Diamond,
-    #[deku(id = "0b100")]
-    None
-}
-
-impl From<u8> for Trump {
-    fn from(value: u8) -> Self {
-        match value {
-            0 => Trump::Spade,
-            1 => Trump::Heart,
-            2 => Trump::Club,
-            3 => Trump::Diamond,
-            4 => Trump::None,
-            _ => panic!("Invalid trump value: {}", value)
-        }
-    }
-}
-
-#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite)]
-struct Player {
-    #[deku(update = "self.name.len()")]
-    name_length: u8,
-    #[deku(count = "name_length")]
-    name: Vec<u8>,
-}
-
-
-#[derive(Debug, PartialEq, DekuRead, DekuWrite)]
-struct Game {
-    name_length: u8,
-    #[deku(count = "name_length")]
-    name: Vec<u8>,
-    #[deku(bits = "3")]
-    n_players: u8,
-    #[deku(count = "n_players")]
-    players: Vec<Player>,
-    #[deku(bits = "3")]
-    start_dealer: u8,
-    #[deku(bits="5")]
-    n_rounds: u8, // should be 19
-    #[deku(bits = "5")]
-    current_round: u8,
-    #[deku(count = "n_rounds")]
-    trumps: Vec<Trump>,
-    #[deku(count = "n_players")]
-    player_bids: Vec<PlayerScore>,
-    tricks: AllTricks
-
-}
-
 fn calculate_needed_bits(n: u8) -> u8 {
     let mut bits = 0;
     let mut n = n;
@@ -296,245 +306,36 @@ fn calculate_needed_bits(n: u8) -> u8 {
     bits
 }
 
-macro_rules! zero_nonzero {
-    ($round:tt, $bits:tt) => {
-        use deku::prelude::*;
-
-    paste::paste! {
-        #[derive(Debug, Clone, Default, PartialEq, DekuRead, DekuWrite)]
-        #[deku(type = "u8", bits = "1")]
-        pub enum [<Value$round>] {
-            #[default]
-            #[deku(id = "0")]
-            Zero,
-            #[deku(id = "1")]
-            NonZero(#[deku(bits = $bits)] u8)
-        }
-        impl From<u8> for [<Value$round>] {
-            fn from(value: u8) -> Self {
-                match value {
-                    0 => [<Value$round>]::Zero,
-                    _ => [<Value$round>]::NonZero(value - 1)
-                }
-            }
-        }
-
-        impl From<[<Value$round>]> for u8 {
-            fn from(value: [<Value$round>]) -> Self {
-                match value {
-                    [<Value$round>]::Zero => 0,
-                    [<Value$round>]::NonZero(v) => v + 1
-                }
-            }
-        }
-    }
-    }
-}
-
-macro_rules! max_three_or_higher {
-    ($round:tt, $bits:tt) => {
-        use deku::prelude::*;
-
-    paste::paste! {
-        #[derive(Debug, Clone, PartialEq, DekuRead, DekuWrite)]
-        #[deku(type = "u8", bits = "1")]
-        pub enum [<Value$round>] {
-            #[deku(id = "0")]
-            MaxThree(#[deku(bits = "2")] u8),
-            #[deku(id = "1")]
-            Higher(#[deku(bits = $bits)] u8)
-        }
-
-        impl Default for [<Value$round>] {
-            fn default() -> Self {
-                [<Value$round>]::MaxThree(0)
-            }
-        }
-
-        impl From<u8> for [<Value$round>] {
-            fn from(value: u8) -> Self {
-                match value {
-                    0..=3 => [<Value$round>]::MaxThree(value),
-                    _ => [<Value$round>]::Higher(value - 3)
-                }
-            }
-        }
-        impl From<[<Value$round>]> for u8 {
-            fn from(value: [<Value$round>]) -> Self {
-                match value {
-                    [<Value$round>]::MaxThree(v) => v,
-                    [<Value$round>]::Higher(v) => v + 3
-                }
-            }
-        }
-    }
-    }
-}
-
-macro_rules! tailored_enum {
-    (1) => { // 10
-        max_three_or_higher!(1, 3);
-    };
-    (2) => { // 9
-        max_three_or_higher!(2, 3);
-    };
-    (3) => { // 8
-        max_three_or_higher!(3, 3);
-    };
-    (4) => { // 7
-        max_three_or_higher!(4, 3);
-    };
-    (5) => { // 6
-        max_three_or_higher!(5, 2);
-    };
-    (6) => { // 5
-        zero_nonzero!(6, 3);
-    };
-    (7) => { // 4
-        zero_nonzero!(7, 2);
-    };
-    (8) => { // 3
-        zero_nonzero!(8, 2);
-    };
-    (9) => { // 2
-        zero_nonzero!(9, 1);
-    };
-    (10) => { // 1
-        zero_nonzero!(10, 1);
-    };
-    (11) => { // 2
-        zero_nonzero!(11, 1);
-    };
-    (12) => { // 3
-        zero_nonzero!(12, 2);
-    };
-    (13) => { // 4
-        zero_nonzero!(13, 2);
-    };
-    (14) => { // 5
-        zero_nonzero!(14, 3);
-    };
-    (15) => { // 6
-        max_three_or_higher!(15, 2);
-    };
-    (16) => { // 7
-        max_three_or_higher!(16, 3);
-    };
-    (17) => { // 8
-        max_three_or_higher!(17, 3);
-    };
-    (18) => { // 9
-        max_three_or_higher!(18, 3);
-    };
-    (19) => { // 10
-        max_three_or_higher!(19, 3);
-    };
-}
-
-macro_rules! player_score {
-    ($name:ident [ $($round_id:tt),* ]) => {
-        paste! {
-            use deku::prelude::*;
-            use std::ops::{Index, IndexMut};
-
-            mod [<values_$name:lower>] {
-                $(
-                tailored_enum!($round_id);
-                )*
-            }
-
-            #[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
-            pub struct $name {
-            $(
-            pub [<round_$round_id>]: [<values_$name:lower>]::[<Value$round_id>],
-            )*
-            }
-
-            impl $name {
-                pub fn get_round(&self, index: u8) -> u8 {
-                    let result: u8 = match index {
-                        $( $round_id => self.[<round_$round_id>].clone().into(), )*
-                        _ => panic!("Invalid index: {}", index)
-                    };
-                    result
-                }
-
-                pub fn set_round(&mut self, index: u8, value: u8) {
-                    match index {
-                        $( $round_id => self.[<round_$round_id>] = value.into(), )*
-                        _ => panic!("Invalid index: {}", index)
-                    }
-                }
-            }
-        }
-    }
-}
-
-player_score!(PlayerScore
-    [1, 2, 3, 4, 5, 6, 7, 8, 9, 10,
-     11, 12, 13, 14, 15, 16, 17, 18, 19]);
-
-
-macro_rules! variable_bit_array {
-    ($name:ident $type:ident [ $(($round_id:tt, $bits:tt)),* ]) => {
-        paste! {
-            use deku::prelude::*;
-
-            #[derive(Debug, Default, PartialEq, DekuRead, DekuWrite)]
-            #[deku(endian="big")]
-            pub struct $name {
-            $(
-            #[deku(bits = $bits)]
-            pub [<round_$round_id>]: $type,
-            )*
-            }
-
-            impl std::ops::Index<u8> for $name {
-                type Output = $type;
-                fn index(&self, index: u8) -> &Self::Output {
-                    match index {
-                        $( $round_id => &self.[<round_$round_id>], )*
-                        _ => panic!("Invalid index: {}", index)
-                    }
-                }
-            }
-
-            impl std::ops::IndexMut<u8> for $name {
-                fn index_mut(&mut self, index: u8) -> &mut Self::Output {
-                    match index {
-                        $( $round_id => &mut self.[<round_$round_id>], )*
-                        _ => panic!("Invalid index: {}", index)
-                    }
-                }
-            }
-        }
-    }
-}
-
-// for 5 players
-variable_bit_array!(AllTricks u16
-    [(1, 15), (2, 14), (3, 13), (4, 12), (5, 11), (6, 10),
-        (7, 9), (8, 8), (9, 7), (10, 6), (11, 7), (12, 8), (13, 9),
-        (14, 10), (15, 11), (16, 12), (17, 13), (18, 14), (19, 15)]);
-
-
-fn serialize_tricks(tricks: &Vec<u8>) -> u16 {
-    let mut result = 1;
+// Unary encoding of the tricks of a round: a 1 followed by `tricks` zeros per player, so it takes
+// n_players + n_cards bits. Returns None when the result does not fit in `bits` bits.
+fn serialize_tricks(tricks: &[u8], bits: u8) -> Option<u32> {
+    let mut result: u32 = 1;
     let mut first = true;
     for trick in tricks.iter() {
         if !first {
-            result <<= 1;
+            result = shift_left(result, 1)?;
         }
         first = false;
         result |= 1;
-        result <<= *trick;
+        result = shift_left(result, *trick)?;
     }
-    result
+    if (bits as u32) < u32::BITS && result >> bits != 0 {
+        return None;
+    }
+    Some(result)
+}
+
+fn shift_left(value: u32, n: u8) -> Option<u32> {
+    let shifted = value.checked_shl(n as u32)?;
+    if shifted >> n != value {
+        return None;
+    }
+    Some(shifted)
 }
 
-fn deserialize_tricks(serialized_tricks: &u16) -> Vec<u8> {
+fn deserialize_tricks(serialized_tricks: u32) -> Vec<u8> {
     let mut result = Vec::new();
-    let mut value = *serialized_tricks;
+    let mut value = serialized_tricks;
     let mut current_trick = 0;
     while value > 0 {
         if value & 1 == 0 {
@@ -558,85 +359,164 @@ fn deserialize_tricks(serialized_tricks: &u16) -> Vec<u8> {
 mod test {
     use std::convert::{TryInto};
     use deku::{DekuContainerRead, DekuContainerWrite};
-    use crate::{AllTricks, calculate_needed_bits, deserialize_tricks, Game, JsGame, JsPlayer, JsRound, Player, serialize_tricks, Trump};
-    use paste::paste;
-    use crate::Trump::{Heart, Spade};
-    use crate::values_playerscore::Value1;
+    use crate::score::Scoring;
+    use crate::{calculate_needed_bits, compact, crc16, decode, deserialize_tricks, encode, legacy, JsGame, JsPlayer, JsRound, FORMAT_VERSION, MAGIC, serialize_tricks, SerializeError, Trump};
 
     #[test]
     fn test_serialize_tricks() {
         let tricks = vec![0, 3, 2, 4, 0];
-        let serialized = serialize_tricks(&tricks);
+        let serialized = serialize_tricks(&tricks, 14).unwrap();
         assert_eq!(0b11000100100001, serialized);
 
-        let original = deserialize_tricks(&serialized);
+        let original = deserialize_tricks(serialized);
         assert_eq!(tricks, original);
     }
 
     #[test]
-    fn test_8bit_tricks_serialize() {
-        let tricks = vec![0, 0, 1, 2, 0];
-        let serialized = serialize_tricks(&tricks);
-        assert_eq!(0b11101001, serialized);
+    fn test_8bit_tricks_deserialize() {
+        let serialized = 0b11101001;
+        let deserialized = deserialize_tricks(serialized);
+        assert_eq!(vec![0, 0, 1, 2, 0], deserialized);
+    }
+
+
+    #[test]
+    fn serialize_enum() {
+        for trump in [Trump::Spade, Trump::Heart, Trump::Club, Trump::Diamond, Trump::None] {
+            let serialized: Vec<u8> = trump.try_into().unwrap();
+            println!("{:?}, {:08b}", serialized, serialized[0]);
+        }
+    }
 
-        let mut container = AllTricks::default();
-        container.round_8 = serialized;
-        let container_serialized = container.to_bytes().unwrap();
-        let (_, container_deserialized) = AllTricks::from_bytes((&container_serialized, 0)).unwrap();
+    /// A game named "Testgame" with standard scoring and a player per name, the ids in order.
+    pub(crate) fn game(names: &[&str], rounds: Vec<JsRound>) -> JsGame {
+        JsGame {
+            game_version: 3,
+            name: "Testgame".to_string(),
+            scoring: Scoring::Standard,
+            players: names.iter().enumerate().map(|(id, name)| JsPlayer { id: id as u8, name: name.to_string() }).collect(),
+            rounds,
+        }
+    }
 
-        let original = deserialize_tricks(&container_deserialized.round_8);
-        assert_eq!(tricks, original);
+    fn test_game() -> JsGame {
+        game(&["Player 1", "Player 2"], vec![
+            JsRound { n_cards: 10, trump: 1, bids: vec![Some(2), Some(3)], tricks: vec![Some(4), Some(6)], dealer_id: 1 },
+            JsRound { n_cards: 9, trump: 4, bids: vec![Some(1), Some(0)], tricks: vec![], dealer_id: 0 },
+        ])
     }
 
     #[test]
-    fn test_8bit_tricks_deserialize() {
-        let serialized = 0b11101001;
-        let deserialized = deserialize_tricks(&serialized);
-        assert_eq!(vec![0, 0, 1, 2, 0], deserialized);
+    fn test_encode_writes_header() {
+        let encoded = encode(&test_game()).unwrap();
+        assert_eq!(MAGIC, encoded[..2]);
+        assert_eq!(FORMAT_VERSION, encoded[2]);
+
+        let decoded = decode(&encoded).unwrap();
+        assert_eq!(decoded.name, "Testgame");
+        assert_eq!(decoded.rounds[0].tricks, vec![Some(4), Some(6)]);
+        assert_eq!(decoded.rounds[1].bids, vec![Some(1), Some(0)]);
     }
 
+    #[test]
+    fn test_decode_legacy_without_header() {
+        let legacy: Vec<u8> = legacy::Game::try_from(test_game()).unwrap().try_into().unwrap();
+        let decoded = decode(&legacy).unwrap();
+        assert_eq!(decoded.players[1].name, "Player 2");
+        assert_eq!(decoded.rounds[0].bids, vec![Some(2), Some(3)]);
+
+        let with_header = [&MAGIC[..], &[1], &legacy].concat();
+        assert_eq!(test_game(), decode(&with_header).unwrap());
+    }
 
     #[test]
-    fn test_variable_bit_array() {
-        player_score!(TestStruct [1, 2]);
-        let _bids = TestStruct::default();
+    fn test_decode_unknown_version() {
+        let mut encoded = encode(&test_game()).unwrap();
+        encoded[2] = 42;
+        assert!(matches!(decode(&encoded), Err(SerializeError::UnsupportedVersion(42))));
     }
 
     #[test]
-    fn serialize_enum() {
-        for trump in vec![Trump::Spade, Trump::Heart, Trump::Club, Trump::Diamond, Trump::None] {
-            let serialized: Vec<u8> = trump.try_into().unwrap();
-            println!("{:?}, {:08b}", serialized, serialized[0]);
+    fn test_serialize_tricks_overflow() {
+        assert_eq!(None, serialize_tricks(&[3, 4], 8));
+        assert_eq!(None, serialize_tricks(&[20, 20], 32));
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        let encoded = encode(&test_game()).unwrap();
+        assert_eq!(Err(SerializeError::ChecksumMismatch), decode(&encoded[..encoded.len() - 3]).map(|_| ()));
+        assert_eq!(Err(SerializeError::Truncated), decode(&encoded[..4]).map(|_| ()));
+        assert_eq!(Err(SerializeError::Truncated), decode(&[]).map(|_| ()));
+
+        let legacy: Vec<u8> = legacy::Game::try_from(test_game()).unwrap().try_into().unwrap();
+        assert_eq!(Err(SerializeError::Truncated), decode(&legacy[..legacy.len() - 3]).map(|_| ()));
+    }
+
+    #[test]
+    fn test_decode_altered() {
+        let encoded = encode(&test_game()).unwrap();
+        for i in 3..encoded.len() {
+            let mut altered = encoded.clone();
+            altered[i] ^= 0b100;
+            assert_eq!(Err(SerializeError::ChecksumMismatch), decode(&altered).map(|_| ()), "byte {}", i);
         }
     }
 
     #[test]
-    fn test_serialize_round_scores() {
-        let js_game = JsGame {
-            game_version: 3,
-            name: "Testgame".to_string(),
-            players: vec![
-                JsPlayer {
-                    id: 0,
-                    name: "Player 1".to_string()
-                },
-                JsPlayer {
-                    id: 1,
-                    name: "Player 2".to_string()
-                },
-            ],
-            rounds: vec![ JsRound {
-                n_cards: 10,
-                trump: 1,
-                bids: vec![2, 3],
-                tricks: vec![4, 6],
-                dealer_id: 0
-            }]
-        };
-
-        let game = Game::from(js_game);
-        assert_eq!(game.player_bids[0].round_1, Value1::MaxThree(2));
-        assert_eq!(game.player_bids[1].round_1, Value1::MaxThree(3));
+    fn test_decode_without_checksum() {
+        let body = compact::Game::try_from(&test_game()).unwrap().to_bytes(3).unwrap();
+        let encoded = [&MAGIC[..], &[3], &body].concat();
+        assert_eq!(test_game(), decode(&encoded).unwrap());
+    }
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(0x29B1, crc16(b"123456789"));
+    }
+
+    #[test]
+    fn test_decode_invalid_name() {
+        let mut game = test_game();
+        game.name = "é".to_string();
+        let body = compact::Game::try_from(&game).unwrap().to_bytes(3).unwrap();
+        let mut encoded = [&MAGIC[..], &[3], &body].concat();
+        // first byte of the name after the header and name length
+        encoded[4] = 0xFF;
+        assert_eq!(Err(SerializeError::InvalidName), decode(&encoded).map(|_| ()));
+    }
+
+    #[test]
+    fn test_encode_errors() {
+        let mut game = test_game();
+        game.rounds[1].trump = 5;
+        assert_eq!(Err(SerializeError::InvalidTrump(5)), encode(&game));
+
+        let mut game = test_game();
+        game.rounds.clear();
+        assert_eq!(Err(SerializeError::NoRounds), encode(&game));
+
+        let mut game = test_game();
+        game.players.clear();
+        assert_eq!(Err(SerializeError::NoPlayers), encode(&game));
+
+        let mut game = test_game();
+        for id in 2..8 {
+            game.players.push(JsPlayer { id, name: format!("Player {}", id + 1) });
+        }
+        assert_eq!(Err(SerializeError::TooManyPlayers(8)), encode(&game));
+
+        let mut game = test_game();
+        game.rounds[0].tricks = vec![Some(10), Some(10)];
+        assert_eq!(Err(SerializeError::TricksOverflow(1)), encode(&game));
+
+        let mut game = test_game();
+        game.rounds[1].bids = vec![Some(1), Some(0), Some(2)];
+        assert_eq!(Err(SerializeError::BidsOverflow(2)), encode(&game));
+
+        let mut game = test_game();
+        game.rounds[0].dealer_id = 2;
+        assert_eq!(Err(SerializeError::InvalidDealer(2)), encode(&game));
     }
 
     #[test]
@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::process::ExitCode;
+
+use serializer::JsGame;
+use serializer::payout::{Cents, Ledger, Payout};
+use serializer::score::score_game;
+use serializer::share::{decode_share, encode_share};
+
+const USAGE: &str = "usage:
+  share decode <share url or base64>      print the game as JSON
+  share encode [file]                     read game JSON from file or stdin, print the base64
+  share standings <share url or base64>   print the leaderboard
+  share settle [--payout <payout>] <share url or base64>...
+                                          print who pays whom after all games
+
+payouts:
+  winner-takes-all                        the default
+  second-place-breaks-even
+  configurable[:<players>=<euros>,...]    amounts per position, player counts without amounts, or
+                                          with a position after the first that receives money,
+                                          use the app's defaults, e.g.
+                                          configurable:3=3,0,-3/4=4.5,0,-1.5,-3";
+
+// euros with at most two decimals, e.g. "-1.5"
+fn parse_cents(euros: &str) -> Option<Cents> {
+    let (whole, fraction) = euros.split_once('.').unwrap_or((euros, ""));
+    if fraction.len() > 2 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let cents: Cents = format!("{:0<2}", fraction).parse().ok()?;
+    let whole: Cents = whole.parse().ok()?;
+    Some(if euros.starts_with('-') { whole * 100 - cents } else { whole * 100 + cents })
+}
+
+fn parse_payout(payout: &str) -> Result<Payout, String> {
+    let invalid = || format!("invalid payout: {}\n\n{}", payout, USAGE);
+    match payout.split_once(':') {
+        None if payout == "winner-takes-all" => Ok(Payout::WinnerTakesAll),
+        None if payout == "second-place-breaks-even" => Ok(Payout::SecondPlaceBreaksEven),
+        None if payout == "configurable" => Ok(Payout::Configurable(BTreeMap::new())),
+        Some(("configurable", amounts)) => {
+            let mut configured = BTreeMap::new();
+            for entry in amounts.split('/') {
+                let (n_players, euros) = entry.split_once('=').ok_or_else(invalid)?;
+                let n_players: usize = n_players.parse().map_err(|_| invalid())?;
+                let amounts = euros.split(',').map(parse_cents).collect::<Option<Vec<_>>>().ok_or_else(invalid)?;
+                configured.insert(n_players, amounts);
+            }
+            Ok(Payout::Configurable(configured))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn standings(game: &JsGame) -> String {
+    score_game(game).leaderboard().iter()
+        .map(|player| format!("{}. {} {}", player.leaderboard_position, game.players[player.id as usize].name, player.score))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn run(args: &[String]) -> Result<String, String> {
+    match args {
+        [command, input] if command == "decode" => {
+            let game = decode_share(input).map_err(|e| e.to_string())?;
+            game.to_json().map_err(|e| e.to_string())
+        }
+        [command, input] if command == "standings" => {
+            let game = decode_share(input).map_err(|e| e.to_string())?;
+            Ok(standings(&game))
+        }
+        [command, inputs @ ..] if command == "settle" && !inputs.is_empty() => {
+            let (payout, inputs) = match inputs {
+                [option, payout, inputs @ ..] if option == "--payout" => (parse_payout(payout)?, inputs),
+                _ => (Payout::WinnerTakesAll, inputs),
+            };
+            if inputs.is_empty() {
+                return Err(USAGE.to_string());
+            }
+
+            let mut ledger = Ledger::new();
+            for input in inputs {
+                let game = decode_share(input).map_err(|e| format!("{}: {}", input, e))?;
+                ledger.add_game(&game, &payout);
+            }
+            let transfers: Vec<String> = ledger.transfers().iter().map(|transfer| transfer.to_string()).collect();
+            Ok(if transfers.is_empty() { "nobody pays anything".to_string() } else { transfers.join("\n") })
+        }
+        [command, rest @ ..] if command == "encode" && rest.len() <= 1 => {
+            let json = match rest.first() {
+                Some(path) => std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?,
+                None => {
+                    let mut json = String::new();
+                    std::io::stdin().read_to_string(&mut json).map_err(|e| e.to_string())?;
+                    json
+                }
+            };
+            let game = JsGame::from_json(&json).map_err(|e| e.to_string())?;
+            encode_share(&game).map_err(|e| e.to_string())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(output) => {
+            println!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_usage_example() {
+        let example = USAGE.lines().map(str::trim).find(|line| line.starts_with("configurable:")).unwrap();
+        let amounts = BTreeMap::from([(3, vec![300, 0, -300]), (4, vec![450, 0, -150, -300])]);
+        assert_eq!(Ok(Payout::Configurable(amounts)), parse_payout(example));
+    }
+
+    #[test]
+    fn test_parse_payout_errors() {
+        assert!(parse_payout("configurable:3=3,0,-3,").is_err());
+        assert!(parse_payout("configurable:3=3.001").is_err());
+        assert!(parse_payout("loser-takes-all").is_err());
+    }
+}
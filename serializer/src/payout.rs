@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use crate::score::{compare_players, score_game};
+use crate::JsGame;
+
+/// Money is counted in euro cents so balances add up exactly.
+pub type Cents = i64;
+
+// above this many players with a balance the transfers are not guaranteed to be minimal
+const MAX_EXACT_SETTLE: usize = 20;
+
+/// How the money of one game is divided over its players, by leaderboard position.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Payout {
+    /// The last place pays €3, everybody else not in first place €1.50, the winners share the pot.
+    WinnerTakesAll,
+    /// Like `WinnerTakesAll`, but second place neither pays nor receives.
+    SecondPlaceBreaksEven,
+    /// Amounts per position, keyed by player count. Player counts without a valid entry use the
+    /// default amounts, an entry is valid when it has an amount for every position and no position
+    /// after the first receives money. The winners share what the other positions pay, which
+    /// stays at least zero however the positions are shared.
+    Configurable(BTreeMap<usize, Vec<Cents>>),
+}
+
+impl Payout {
+    fn amounts(amounts: &BTreeMap<usize, Vec<Cents>>, n_players: usize) -> Vec<Cents> {
+        match amounts.get(&n_players) {
+            Some(amounts) if (2..=5).contains(&n_players) && amounts.len() == n_players
+                && amounts[1..].iter().all(|&amount| amount <= 0) => amounts.clone(),
+            _ => match n_players {
+                2 => vec![150, -150],
+                3 => vec![300, 0, -300],
+                4 => vec![450, 0, -150, -300],
+                5 => vec![600, 0, -150, -150, -300],
+                _ => vec![0; n_players],
+            },
+        }
+    }
+
+    // what a player who is not a winner pays, as a negative amount
+    fn amount(&self, position: usize, last_position: usize, n_players: usize) -> Cents {
+        match self {
+            _ if position == last_position => match self {
+                Payout::Configurable(amounts) => Payout::amounts(amounts, n_players)[n_players - 1],
+                _ => -300,
+            },
+            Payout::WinnerTakesAll => -150,
+            Payout::SecondPlaceBreaksEven if position == 2 => 0,
+            Payout::SecondPlaceBreaksEven => -150,
+            Payout::Configurable(amounts) => Payout::amounts(amounts, n_players)[position - 1],
+        }
+    }
+}
+
+/// Leaderboard positions by player id. Players with the same score share a position, the next
+/// position skips the shared places.
+pub fn positions(game: &JsGame) -> Vec<usize> {
+    let scores = score_game(game);
+    let mut players: Vec<_> = scores.players.iter().collect();
+    players.sort_by(|a, b| compare_players(a, b));
+
+    let mut positions = vec![0; players.len()];
+    let mut position = 1;
+    for (index, player) in players.iter().enumerate() {
+        if index > 0 && players[index - 1].score != player.score {
+            position = index + 1;
+        }
+        positions[player.id as usize] = position;
+    }
+    positions
+}
+
+/// Money won (positive) or paid (negative) by every player of a game, by player id.
+/// The winners split the pot; leftover cents go to the winners with the lowest ids.
+pub fn game_payouts(game: &JsGame, payout: &Payout) -> Vec<Cents> {
+    let positions = positions(game);
+    let n_players = positions.len();
+    let last_position = positions.iter().copied().max().unwrap_or(0);
+
+    let mut payouts = vec![0; n_players];
+    let mut pot = 0;
+    for (player, &position) in positions.iter().enumerate() {
+        if position != 1 {
+            payouts[player] = payout.amount(position, last_position, n_players);
+            pot += payouts[player];
+        }
+    }
+
+    // the winners receive what the others pay, so every game adds up to zero
+    let pot = -pot;
+    let winners: Vec<usize> = (0..n_players).filter(|&player| positions[player] == 1).collect();
+    for (index, &winner) in winners.iter().enumerate() {
+        let remainder = pot.rem_euclid(winners.len() as Cents);
+        payouts[winner] = pot.div_euclid(winners.len() as Cents) + (index < remainder as usize) as Cents;
+    }
+    payouts
+}
+
+/// One payment between two players to settle the ledger.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transfer {
+    pub from: String,
+    pub to: String,
+    pub amount: Cents,
+}
+
+impl Display for Transfer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} pays {} €{}.{:02}", self.from, self.to, self.amount / 100, self.amount % 100)
+    }
+}
+
+/// Balances of players across games. Players are matched by name, like the totals in the app.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Ledger {
+    balances: BTreeMap<String, Cents>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_game(&mut self, game: &JsGame, payout: &Payout) {
+        for (player, amount) in game.players.iter().zip(game_payouts(game, payout)) {
+            *self.balances.entry(player.name.clone()).or_insert(0) += amount;
+        }
+    }
+
+    pub fn balances(&self) -> &BTreeMap<String, Cents> {
+        &self.balances
+    }
+
+    /// The fewest transfers that bring every balance to zero.
+    pub fn transfers(&self) -> Vec<Transfer> {
+        let open: Vec<(&String, Cents)> = self.balances.iter()
+            .filter(|(_, &balance)| balance != 0)
+            .map(|(name, &balance)| (name, balance))
+            .collect();
+
+        // players split into as many groups that add up to zero as possible, each group of n
+        // players settles with n - 1 transfers
+        let mut transfers = Vec::new();
+        for group in zero_sum_groups(&open.iter().map(|(_, balance)| *balance).collect::<Vec<_>>()) {
+            let group: Vec<(&String, Cents)> = group.into_iter().map(|index| open[index]).collect();
+            settle_group(group, &mut transfers);
+        }
+        transfers
+    }
+}
+
+// Indices of the balances split into the most groups that sum to zero. Found by dynamic
+// programming over subsets: every zero sum subset on the way to the full set closes a group.
+fn zero_sum_groups(balances: &[Cents]) -> Vec<Vec<usize>> {
+    let n = balances.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    if n > MAX_EXACT_SETTLE {
+        return vec![(0..n).collect()];
+    }
+
+    let full = (1usize << n) - 1;
+    let mut sums = vec![0; full + 1];
+    let mut groups = vec![0u32; full + 1];
+    for mask in 1..=full {
+        let lowest = mask.trailing_zeros() as usize;
+        sums[mask] = sums[mask & (mask - 1)] + balances[lowest];
+        let best = (0..n).filter(|i| mask & (1 << i) != 0).map(|i| groups[mask ^ (1 << i)]).max().unwrap_or(0);
+        groups[mask] = best + (sums[mask] == 0) as u32;
+    }
+
+    // walk back from the full set, the removed indices in reverse form the groups in order
+    let mut order = Vec::with_capacity(n);
+    let mut mask = full;
+    while mask != 0 {
+        let next = (0..n)
+            .filter(|i| mask & (1 << i) != 0)
+            .max_by_key(|i| (groups[mask ^ (1 << i)], std::cmp::Reverse(*i)))
+            .unwrap();
+        order.push(next);
+        mask ^= 1 << next;
+    }
+    order.reverse();
+
+    let mut result = Vec::new();
+    let mut group = Vec::new();
+    let mut sum = 0;
+    for index in order {
+        group.push(index);
+        sum += balances[index];
+        if sum == 0 {
+            result.push(std::mem::take(&mut group));
+        }
+    }
+    if !group.is_empty() {
+        result.push(group);
+    }
+    result
+}
+
+// largest debt paid to the largest credit first
+fn settle_group(group: Vec<(&String, Cents)>, transfers: &mut Vec<Transfer>) {
+    let (mut creditors, debtors): (Vec<_>, Vec<_>) = group.into_iter().partition(|(_, balance)| *balance > 0);
+    let mut debtors: Vec<(&String, Cents)> = debtors.into_iter().map(|(name, balance)| (name, -balance)).collect();
+
+    while let (Some(creditor), Some(debtor)) = (
+        (0..creditors.len()).max_by_key(|&i| (creditors[i].1, std::cmp::Reverse(i))),
+        (0..debtors.len()).max_by_key(|&i| (debtors[i].1, std::cmp::Reverse(i))),
+    ) {
+        let amount = creditors[creditor].1.min(debtors[debtor].1);
+        transfers.push(Transfer { from: debtors[debtor].0.clone(), to: creditors[creditor].0.clone(), amount });
+        creditors[creditor].1 -= amount;
+        debtors[debtor].1 -= amount;
+        creditors.retain(|(_, balance)| *balance > 0);
+        debtors.retain(|(_, balance)| *balance > 0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use crate::payout::{game_payouts, positions, Ledger, Payout, Transfer};
+    use crate::score::Scoring;
+    use crate::test::game;
+    use crate::{JsGame, JsRound};
+
+    // one round in which every player bids one
+    fn game_with_tricks(names: &[&str], tricks: &[u8]) -> JsGame {
+        let round = JsRound {
+            n_cards: tricks.iter().sum(),
+            trump: 0,
+            bids: vec![Some(1); names.len()],
+            tricks: tricks.iter().map(|&t| Some(t)).collect(),
+            dealer_id: 0,
+        };
+        JsGame { scoring: Scoring::MissPenalty, ..game(names, vec![round]) }
+    }
+
+    #[test]
+    fn test_positions() {
+        // miss penalty scores: -1, 6, -1, -3
+        let game = game_with_tricks(&["Anna", "Piet", "Joël", "Kees"], &[0, 1, 2, 4]);
+        assert_eq!(vec![2, 1, 2, 4], positions(&game));
+    }
+
+    #[test]
+    fn test_winner_takes_all() {
+        let game = game_with_tricks(&["Anna", "Piet", "Joël", "Kees"], &[0, 1, 2, 4]);
+        assert_eq!(vec![-150, 600, -150, -300], game_payouts(&game, &Payout::WinnerTakesAll));
+    }
+
+    #[test]
+    fn test_second_place_breaks_even() {
+        let game = game_with_tricks(&["Anna", "Piet", "Joël", "Kees"], &[0, 1, 2, 4]);
+        assert_eq!(vec![0, 300, 0, -300], game_payouts(&game, &Payout::SecondPlaceBreaksEven));
+
+        // second place is also the last place
+        let game = game_with_tricks(&["Anna", "Piet", "Joël"], &[1, 0, 0]);
+        assert_eq!(vec![600, -300, -300], game_payouts(&game, &Payout::SecondPlaceBreaksEven));
+    }
+
+    #[test]
+    fn test_configurable_amounts() {
+        let game = game_with_tricks(&["Anna", "Piet", "Joël", "Kees"], &[0, 1, 2, 4]);
+        assert_eq!(vec![0, 300, 0, -300], game_payouts(&game, &Payout::Configurable(BTreeMap::new())));
+
+        let amounts = BTreeMap::from([(4, vec![0, -100, -200, -500]), (3, vec![1, 2])]);
+        assert_eq!(vec![-100, 700, -100, -500], game_payouts(&game, &Payout::Configurable(amounts.clone())));
+
+        // the amounts for 3 players are invalid
+        let game = game_with_tricks(&["Anna", "Piet", "Joël"], &[0, 1, 3]);
+        assert_eq!(vec![0, 300, -300], game_payouts(&game, &Payout::Configurable(amounts)));
+    }
+
+    #[test]
+    fn test_winners_split_the_pot() {
+        let game = game_with_tricks(&["Anna", "Piet", "Joël", "Kees"], &[1, 1, 1, 0]);
+        assert_eq!(vec![100, 100, 100, -300], game_payouts(&game, &Payout::WinnerTakesAll));
+
+        let game = game_with_tricks(&["Anna", "Piet", "Joël", "Kees", "Mies"], &[1, 1, 1, 0, 3]);
+        assert_eq!(vec![150, 150, 150, -150, -300], game_payouts(&game, &Payout::WinnerTakesAll));
+
+        let game = game_with_tricks(&["Anna", "Piet", "Joël"], &[1, 1, 0]);
+        let amounts = BTreeMap::from([(3, vec![0, -100, -101])]);
+        assert_eq!(vec![51, 50, -101], game_payouts(&game, &Payout::Configurable(amounts)));
+
+        let game = game_with_tricks(&["Anna", "Piet", "Joël", "Kees", "Mies", "Wim", "Gijs"], &[1, 1, 1, 0, 0, 0, 0]);
+        assert_eq!(vec![400, 400, 400, -300, -300, -300, -300], game_payouts(&game, &Payout::WinnerTakesAll));
+    }
+
+    #[test]
+    fn test_ledger() {
+        let mut ledger = Ledger::new();
+        ledger.add_game(&game_with_tricks(&["Anna", "Piet", "Joël"], &[1, 0, 3]), &Payout::WinnerTakesAll);
+        ledger.add_game(&game_with_tricks(&["Piet", "Anna", "Kees"], &[1, 0, 3]), &Payout::WinnerTakesAll);
+
+        let balances: Vec<(&str, i64)> = ledger.balances().iter().map(|(name, &balance)| (name.as_str(), balance)).collect();
+        assert_eq!(vec![("Anna", 300), ("Joël", -300), ("Kees", -300), ("Piet", 300)], balances);
+        assert_eq!(vec![
+            Transfer { from: "Kees".to_string(), to: "Piet".to_string(), amount: 300 },
+            Transfer { from: "Joël".to_string(), to: "Anna".to_string(), amount: 300 },
+        ], ledger.transfers());
+        assert_eq!("Kees pays Piet €3.00", ledger.transfers()[0].to_string());
+    }
+
+    #[test]
+    fn test_ledger_adds_up_to_zero() {
+        // a position after the winner receives, the default amounts are used
+        let amounts = Payout::Configurable(BTreeMap::from([(3, vec![0, 200, -300]), (4, vec![0, 200, -250, -300])]));
+        let game = game_with_tricks(&["Anna", "Piet", "Joël"], &[1, 0, 3]);
+        assert_eq!(vec![300, 0, -300], game_payouts(&game, &amounts));
+
+        let mut ledger = Ledger::new();
+        ledger.add_game(&game, &amounts);
+        // the second and third place share a position, the winner still receives
+        let game = game_with_tricks(&["Piet", "Anna", "Kees", "Mies"], &[1, 0, 0, 3]);
+        assert_eq!(vec![300, 0, 0, -300], game_payouts(&game, &amounts));
+        ledger.add_game(&game, &amounts);
+        assert_eq!(0, ledger.balances().values().sum::<i64>());
+        let paid: i64 = ledger.transfers().iter().map(|transfer| transfer.amount).sum();
+        assert_eq!(ledger.balances().values().filter(|&&balance| balance > 0).sum::<i64>(), paid);
+    }
+
+    #[test]
+    fn test_transfers_are_minimal() {
+        // no group smaller than everybody settles on its own
+        let ledger = Ledger {
+            balances: BTreeMap::from([
+                ("Anna".to_string(), 500),
+                ("Piet".to_string(), 400),
+                ("Joël".to_string(), -300),
+                ("Kees".to_string(), -600),
+                ("Mies".to_string(), 0),
+            ]),
+        };
+        assert_eq!(3, ledger.transfers().len());
+
+        let ledger = Ledger {
+            balances: BTreeMap::from([
+                ("Anna".to_string(), 400),
+                ("Piet".to_string(), 300),
+                ("Joël".to_string(), 300),
+                ("Kees".to_string(), -600),
+                ("Mies".to_string(), -400),
+            ]),
+        };
+        // largest debt to largest credit first would need four transfers
+        let transfers = ledger.transfers();
+        assert_eq!(3, transfers.len());
+        assert!(transfers.contains(&Transfer { from: "Mies".to_string(), to: "Anna".to_string(), amount: 400 }));
+        assert!(Ledger::new().transfers().is_empty());
+    }
+}
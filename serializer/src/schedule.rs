@@ -0,0 +1,71 @@
+use deku::prelude::*;
+
+// card counts are 5 bit fields
+pub(crate) const MAX_CARDS: u8 = 31;
+
+/// The number of cards dealt in each round. Common schedules are stored by id, anything else
+/// as an explicit card count per round.
+#[derive(Clone, Debug, PartialEq, DekuRead, DekuWrite)]
+#[deku(type = "u8", bits = "2", ctx = "n_rounds: u8")]
+pub(crate) enum Schedule {
+    /// From the given number of cards down to 1 and back up again.
+    #[deku(id = "0")]
+    HeenEnTerug(#[deku(bits = "5")] u8),
+    /// From the given number of cards down to 1.
+    #[deku(id = "1")]
+    Heen(#[deku(bits = "5")] u8),
+    #[deku(id = "2")]
+    Custom(#[deku(count = "n_rounds", bits = "5")] Vec<u8>),
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule::HeenEnTerug(10)
+    }
+}
+
+impl Schedule {
+    pub(crate) fn from_cards(cards: &[u8]) -> Self {
+        let start = cards.first().copied().unwrap_or(0);
+        let heen_en_terug = Schedule::HeenEnTerug(start);
+        if heen_en_terug.cards() == cards {
+            return heen_en_terug;
+        }
+
+        let heen = Schedule::Heen(start);
+        if heen.cards() == cards {
+            return heen;
+        }
+
+        Schedule::Custom(cards.to_vec())
+    }
+
+    pub(crate) fn cards(&self) -> Vec<u8> {
+        match self {
+            Schedule::HeenEnTerug(start) => (1..=*start).rev().chain(2..=*start).collect(),
+            Schedule::Heen(start) => (1..=*start).rev().collect(),
+            Schedule::Custom(cards) => cards.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::schedule::Schedule;
+
+    #[test]
+    fn test_schedule_cards() {
+        assert_eq!(vec![10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10], Schedule::default().cards());
+        assert_eq!(vec![3, 2, 1, 2, 3], Schedule::HeenEnTerug(3).cards());
+        assert_eq!(vec![3, 2, 1], Schedule::Heen(3).cards());
+    }
+
+    #[test]
+    fn test_schedule_from_cards() {
+        assert_eq!(Schedule::HeenEnTerug(7), Schedule::from_cards(&[7, 6, 5, 4, 3, 2, 1, 2, 3, 4, 5, 6, 7]));
+        assert_eq!(Schedule::Heen(5), Schedule::from_cards(&[5, 4, 3, 2, 1]));
+        assert_eq!(Schedule::HeenEnTerug(1), Schedule::from_cards(&[1]));
+        assert_eq!(Schedule::Custom(vec![5, 5, 5]), Schedule::from_cards(&[5, 5, 5]));
+        assert_eq!(Schedule::Custom(vec![10, 9, 8]), Schedule::from_cards(&[10, 9, 8]));
+    }
+}
@@ -0,0 +1,257 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+use serde::Serialize;
+pub use crate::fields::Scoring;
+use crate::{JsGame, JsRound};
+
+impl Scoring {
+    pub fn round_score(&self, bid: u8, tricks: u8, n_cards: u8) -> i32 {
+        let (bid, tricks) = (bid as i32, tricks as i32);
+        match self {
+            Scoring::Standard => if bid == tricks { 5 + bid } else { tricks },
+            Scoring::MissPenalty => if bid == tricks { 5 + bid } else { -(bid - tricks).abs() },
+            Scoring::TenPlusBid => if bid == tricks { 10 + bid } else { 0 },
+            Scoring::ZeroBidBonus => match (bid, tricks) {
+                (0, 0) => 5 + n_cards as i32,
+                _ => Scoring::Standard.round_score(bid as u8, tricks as u8, n_cards),
+            },
+        }
+    }
+}
+
+impl FromStr for Scoring {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(Scoring::Standard),
+            "missPenalty" => Ok(Scoring::MissPenalty),
+            "tenPlusBid" => Ok(Scoring::TenPlusBid),
+            "zeroBidBonus" => Ok(Scoring::ZeroBidBonus),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Standing of one player after the last scored round.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerScore {
+    pub id: u8,
+    pub score: i32,
+    pub n_correct_bids: u32,
+    pub highest_round_score: i32,
+    pub highest_round_tricks: u8,
+    /// 1-based, ties are broken by player id.
+    pub leaderboard_position: usize,
+    /// Position before the last scored round, `None` until two rounds are scored.
+    pub previous_leaderboard_position: Option<usize>,
+}
+
+/// Scores of one round, indexed by player id.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoundScore {
+    pub scores: Vec<i32>,
+    pub total_scores: Vec<i32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameScore {
+    /// Indexed by player id.
+    pub players: Vec<PlayerScore>,
+    /// One entry per round, `None` for rounds without every bid and trick count.
+    pub rounds: Vec<Option<RoundScore>>,
+}
+
+impl GameScore {
+    /// Players from first to last place.
+    pub fn leaderboard(&self) -> Vec<&PlayerScore> {
+        let mut players: Vec<&PlayerScore> = self.players.iter().collect();
+        players.sort_by_key(|player| player.leaderboard_position);
+        players
+    }
+}
+
+/// Higher score first, then more correct bids, the most tricks in one round and the highest
+/// score in one round.
+pub fn compare_players(a: &PlayerScore, b: &PlayerScore) -> Ordering {
+    b.score.cmp(&a.score)
+        .then(b.n_correct_bids.cmp(&a.n_correct_bids))
+        .then(b.highest_round_tricks.cmp(&a.highest_round_tricks))
+        .then(b.highest_round_score.cmp(&a.highest_round_score))
+}
+
+// bids and tricks of every player, or None while the round is not complete
+fn entered_round(round: &JsRound, n_players: usize) -> Option<Vec<(u8, u8)>> {
+    (0..n_players)
+        .map(|player| Some((round.bids.get(player).copied()??, round.tricks.get(player).copied()??)))
+        .collect()
+}
+
+/// Score a game with its scoring rules: rounds count once every player has a bid and a trick
+/// count, the leaderboard is recalculated after every scored round.
+pub fn score_game(game: &JsGame) -> GameScore {
+    let n_players = game.players.len();
+    let mut players: Vec<PlayerScore> = (0..n_players).map(|id| PlayerScore {
+        id: id as u8,
+        score: 0,
+        n_correct_bids: 0,
+        highest_round_score: 0,
+        highest_round_tricks: 0,
+        leaderboard_position: id + 1,
+        previous_leaderboard_position: None,
+    }).collect();
+
+    let mut rounds = Vec::with_capacity(game.rounds.len());
+    // before the first scored round the positions are by player id, not a standing
+    let mut scored_any = false;
+    for round in &game.rounds {
+        let Some(entered) = entered_round(round, n_players) else {
+            rounds.push(None);
+            continue;
+        };
+
+        let mut scores = Vec::with_capacity(n_players);
+        for (player, (bid, tricks)) in players.iter_mut().zip(entered) {
+            let score = game.scoring.round_score(bid, tricks, round.n_cards);
+            if bid == tricks {
+                player.n_correct_bids += 1;
+            }
+            player.score += score;
+            player.highest_round_score = player.highest_round_score.max(score);
+            player.highest_round_tricks = player.highest_round_tricks.max(tricks);
+            scores.push(score);
+        }
+
+        let mut standings: Vec<usize> = (0..n_players).collect();
+        standings.sort_by(|&a, &b| compare_players(&players[a], &players[b]));
+        for (index, &player) in standings.iter().enumerate() {
+            if scored_any {
+                players[player].previous_leaderboard_position = Some(players[player].leaderboard_position);
+            }
+            players[player].leaderboard_position = index + 1;
+        }
+        scored_any = true;
+
+        rounds.push(Some(RoundScore {
+            scores,
+            total_scores: players.iter().map(|player| player.score).collect(),
+        }));
+    }
+
+    GameScore { players, rounds }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::game;
+    use crate::{JsGame, JsRound};
+    use crate::score::{compare_players, score_game, PlayerScore, RoundScore, Scoring};
+
+    fn test_game() -> JsGame {
+        game(&["Player 1", "Player 2", "Player 3"], vec![
+            JsRound { n_cards: 3, trump: 0, bids: vec![Some(1), Some(1), Some(0)], tricks: vec![Some(1), Some(2), Some(0)], dealer_id: 0 },
+            JsRound { n_cards: 2, trump: 1, bids: vec![Some(0), Some(1), Some(0)], tricks: vec![Some(0), Some(0), Some(2)], dealer_id: 1 },
+            JsRound { n_cards: 1, trump: 2, bids: vec![Some(1), None, Some(0)], tricks: vec![], dealer_id: 2 },
+        ])
+    }
+
+    #[test]
+    fn test_round_score() {
+        let scores = |scoring: Scoring| [(0, 0), (3, 3), (3, 2), (1, 4)].map(|(bid, tricks)| scoring.round_score(bid, tricks, 7));
+        assert_eq!([5, 8, 2, 4], scores(Scoring::Standard));
+        assert_eq!([5, 8, -1, -3], scores(Scoring::MissPenalty));
+        assert_eq!([10, 13, 0, 0], scores(Scoring::TenPlusBid));
+        assert_eq!([12, 8, 2, 4], scores(Scoring::ZeroBidBonus));
+    }
+
+    #[test]
+    fn test_parse_scoring() {
+        assert_eq!(Ok(Scoring::MissPenalty), "missPenalty".parse());
+        assert_eq!(Err(()), "miss".parse::<Scoring>());
+    }
+
+    #[test]
+    fn test_score_game() {
+        let scores = score_game(&test_game());
+        assert_eq!(Some(RoundScore { scores: vec![6, 2, 5], total_scores: vec![6, 2, 5] }), scores.rounds[0]);
+        assert_eq!(Some(RoundScore { scores: vec![5, 0, 2], total_scores: vec![11, 2, 7] }), scores.rounds[1]);
+        assert_eq!(None, scores.rounds[2]);
+
+        let first = &scores.players[0];
+        assert_eq!((11, 2, 6, 1), (first.score, first.n_correct_bids, first.highest_round_score, first.highest_round_tricks));
+        let positions: Vec<(usize, Option<usize>)> = scores.players.iter()
+            .map(|p| (p.leaderboard_position, p.previous_leaderboard_position))
+            .collect();
+        assert_eq!(vec![(1, Some(1)), (3, Some(3)), (2, Some(2))], positions);
+
+        // after one scored round there is no earlier standing
+        let mut game = test_game();
+        game.rounds.truncate(1);
+        let positions: Vec<(usize, Option<usize>)> = score_game(&game).players.iter()
+            .map(|p| (p.leaderboard_position, p.previous_leaderboard_position))
+            .collect();
+        assert_eq!(vec![(1, None), (3, None), (2, None)], positions);
+    }
+
+    #[test]
+    fn test_score_game_with_miss_penalty() {
+        let mut game = test_game();
+        game.scoring = Scoring::MissPenalty;
+        let scores = score_game(&game);
+        assert_eq!(Some(RoundScore { scores: vec![6, -1, 5], total_scores: vec![6, -1, 5] }), scores.rounds[0]);
+        assert_eq!(Some(RoundScore { scores: vec![5, -1, -2], total_scores: vec![11, -2, 3] }), scores.rounds[1]);
+        let positions: Vec<usize> = scores.players.iter().map(|p| p.leaderboard_position).collect();
+        assert_eq!(vec![1, 3, 2], positions);
+    }
+
+    #[test]
+    fn test_tie_breaks() {
+        let player = |id, score, n_correct_bids, highest_round_tricks, highest_round_score| PlayerScore {
+            id,
+            score,
+            n_correct_bids,
+            highest_round_score,
+            highest_round_tricks,
+            leaderboard_position: 0,
+            previous_leaderboard_position: None,
+        };
+        let mut players = [
+            player(0, 20, 2, 4, 9),
+            player(1, 20, 2, 4, 10),
+            player(2, 20, 2, 5, 7),
+            player(3, 20, 3, 1, 6),
+            player(4, 21, 0, 0, 5),
+            player(5, 20, 2, 4, 9),
+        ];
+        players.sort_by(compare_players);
+        let ids: Vec<u8> = players.iter().map(|p| p.id).collect();
+        assert_eq!(vec![4, 3, 2, 1, 0, 5], ids);
+    }
+
+    #[test]
+    fn test_equal_players_keep_their_order() {
+        let mut game = test_game();
+        game.rounds.truncate(1);
+        game.rounds[0].bids = vec![Some(1), Some(1), Some(1)];
+        game.rounds[0].tricks = vec![Some(1), Some(1), Some(1)];
+
+        let ids: Vec<u8> = score_game(&game).leaderboard().iter().map(|p| p.id).collect();
+        assert_eq!(vec![0, 1, 2], ids);
+    }
+
+    #[test]
+    fn test_unscored_game() {
+        let mut game = test_game();
+        game.rounds[0].tricks[1] = None;
+        game.rounds.truncate(1);
+
+        let scores = score_game(&game);
+        assert_eq!(vec![None], scores.rounds);
+        let positions: Vec<usize> = scores.players.iter().map(|p| p.leaderboard_position).collect();
+        assert_eq!(vec![1, 2, 3], positions);
+        assert!(scores.players.iter().all(|p| p.score == 0 && p.previous_leaderboard_position.is_none()));
+    }
+}
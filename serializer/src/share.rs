@@ -0,0 +1,68 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use crate::{decode, encode, JsGame, SerializeError};
+
+// Shared games are opened through this route of the app, followed by the Base64 encoded game.
+const SHARE_PATH: &str = "#/s/1/";
+
+/// Encode a game as the URL safe Base64 string the app puts in share links.
+pub fn encode_share(game: &JsGame) -> Result<String, SerializeError> {
+    Ok(URL_SAFE_NO_PAD.encode(encode(game)?))
+}
+
+/// Decode a share link, or just the Base64 part of one. Both Base64 alphabets are accepted,
+/// with or without padding.
+pub fn decode_share(input: &str) -> Result<JsGame, SerializeError> {
+    let data = match input.find(SHARE_PATH) {
+        Some(index) => &input[index + SHARE_PATH.len()..],
+        None => input,
+    };
+    let data = data.trim().trim_end_matches('=').replace('+', "-").replace('/', "_");
+
+    let bytes = URL_SAFE_NO_PAD.decode(data).map_err(|e| SerializeError::Base64(e.to_string()))?;
+    decode(&bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::share::{decode_share, encode_share};
+    use crate::test::game;
+    use crate::{JsGame, JsRound, SerializeError};
+
+    fn test_game() -> JsGame {
+        game(&["Anna", "Piet", "Joël"], vec![
+            JsRound { n_cards: 3, trump: 2, bids: vec![Some(1), Some(0), Some(1)], tricks: vec![Some(2), Some(0), Some(1)], dealer_id: 0 },
+            JsRound { n_cards: 2, trump: 0, bids: vec![Some(0), Some(1), Some(0)], tricks: vec![], dealer_id: 1 },
+            JsRound { n_cards: 1, trump: 3, bids: vec![], tricks: vec![], dealer_id: 2 },
+        ])
+    }
+
+    #[test]
+    fn test_share_round_trip() {
+        let shared = encode_share(&test_game()).unwrap();
+        assert!(!shared.contains(['+', '/', '=']));
+        assert_eq!(test_game(), decode_share(&shared).unwrap());
+    }
+
+    #[test]
+    fn test_decode_share_url() {
+        let shared = encode_share(&test_game()).unwrap();
+        let url = format!("https://raymond.k3n.nl/heen-en-terug-score-app/#/s/1/{}\n", shared);
+        assert_eq!(test_game(), decode_share(&url).unwrap());
+    }
+
+    #[test]
+    fn test_decode_share_standard_alphabet() {
+        let shared = encode_share(&test_game()).unwrap();
+        let mut standard = shared.replace('-', "+").replace('_', "/");
+        while !standard.len().is_multiple_of(4) {
+            standard.push('=');
+        }
+        assert_eq!(test_game(), decode_share(&standard).unwrap());
+    }
+
+    #[test]
+    fn test_decode_share_invalid() {
+        assert!(matches!(decode_share("not base64!"), Err(SerializeError::Base64(_))));
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt::Display;
+use serde::Serialize;
+use crate::schedule::MAX_CARDS;
+use crate::{JsGame, JsRound, MAX_NAME_BYTES, MAX_PLAYERS, MAX_ROUNDS};
+
+const DECK_SIZE: usize = 52;
+const MAX_TRUMP: u8 = 4;
+
+/// A rule broken by a game. `round` is the index in `JsGame::rounds`, `player` the player id;
+/// both are `None` when the violation is not tied to a round or player.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Violation {
+    pub round: Option<usize>,
+    pub player: Option<u8>,
+    pub rule: Rule,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum Rule {
+    NoPlayers,
+    TooManyPlayers { n_players: usize },
+    /// The name of the game, or of the player when given, does not fit in the encoded format.
+    NameTooLong { n_bytes: usize },
+    NoRounds,
+    TooManyRounds { n_rounds: usize },
+    TooManyCards { n_cards: u8 },
+    NotEnoughCards { n_cards: u8 },
+    InvalidTrump { trump: u8 },
+    InvalidDealer { dealer_id: u8 },
+    /// The dealer is not the next player after the previous round's dealer.
+    DealerOutOfTurn { dealer_id: u8, expected: u8 },
+    WrongNumberOfBids { n_bids: usize },
+    WrongNumberOfTricks { n_tricks: usize },
+    TricksWithoutBids,
+    BidTooHigh { bid: u8, n_cards: u8 },
+    TricksTooHigh { tricks: u8, n_cards: u8 },
+    TricksDoNotAddUp { total: usize, n_cards: u8 },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(round) = self.round {
+            write!(f, "round {}: ", round + 1)?;
+        }
+        if let Some(player) = self.player {
+            write!(f, "player {}: ", player + 1)?;
+        }
+
+        match &self.rule {
+            Rule::NoPlayers => write!(f, "game has no players"),
+            Rule::TooManyPlayers { n_players } => write!(f, "{} players, at most {} are supported", n_players, MAX_PLAYERS),
+            Rule::NameTooLong { n_bytes } => write!(f, "name is {} bytes, at most {} are supported", n_bytes, MAX_NAME_BYTES),
+            Rule::NoRounds => write!(f, "game has no rounds"),
+            Rule::TooManyRounds { n_rounds } => write!(f, "{} rounds, at most {} are supported", n_rounds, MAX_ROUNDS),
+            Rule::TooManyCards { n_cards } => write!(f, "{} cards, at most {} are supported", n_cards, MAX_CARDS),
+            Rule::NotEnoughCards { n_cards } => write!(f, "not enough cards to deal {} to every player", n_cards),
+            Rule::InvalidTrump { trump } => write!(f, "invalid trump {}", trump),
+            Rule::InvalidDealer { dealer_id } => write!(f, "dealer {} is not a player", dealer_id),
+            Rule::DealerOutOfTurn { dealer_id, expected } => write!(f, "dealer is {}, expected {}", dealer_id, expected),
+            Rule::WrongNumberOfBids { n_bids } => write!(f, "{} bids do not match the number of players", n_bids),
+            Rule::WrongNumberOfTricks { n_tricks } => write!(f, "{} tricks do not match the number of players", n_tricks),
+            Rule::TricksWithoutBids => write!(f, "tricks entered without bids"),
+            Rule::BidTooHigh { bid, n_cards } => write!(f, "bid {} is more than {} cards", bid, n_cards),
+            Rule::TricksTooHigh { tricks, n_cards } => write!(f, "{} tricks is more than {} cards", tricks, n_cards),
+            Rule::TricksDoNotAddUp { total, n_cards } => write!(f, "tricks add up to {} instead of {}", total, n_cards),
+        }
+    }
+}
+
+/// Check a game against the rules of the game and the limits of the encoded format.
+/// Bids and tricks that have not been entered yet are not violations.
+pub fn validate_game(game: &JsGame) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut game_violation = |player, rule| violations.push(Violation { round: None, player, rule });
+
+    let n_players = game.players.len();
+    if n_players == 0 {
+        game_violation(None, Rule::NoPlayers);
+    } else if n_players > MAX_PLAYERS {
+        game_violation(None, Rule::TooManyPlayers { n_players });
+    }
+    if game.name.len() > MAX_NAME_BYTES {
+        game_violation(None, Rule::NameTooLong { n_bytes: game.name.len() });
+    }
+    for player in game.players.iter().filter(|player| player.name.len() > MAX_NAME_BYTES) {
+        game_violation(Some(player.id), Rule::NameTooLong { n_bytes: player.name.len() });
+    }
+
+    if game.rounds.is_empty() {
+        game_violation(None, Rule::NoRounds);
+    } else if game.rounds.len() > MAX_ROUNDS {
+        game_violation(None, Rule::TooManyRounds { n_rounds: game.rounds.len() });
+    }
+
+    let mut previous_dealer: Option<u8> = None;
+    for (round_index, round) in game.rounds.iter().enumerate() {
+        validate_round(round_index, round, n_players, previous_dealer, &mut violations);
+        previous_dealer = Some(round.dealer_id);
+    }
+
+    violations
+}
+
+fn validate_round(round_index: usize, round: &JsRound, n_players: usize, previous_dealer: Option<u8>, violations: &mut Vec<Violation>) {
+    let mut round_violation = |player, rule| violations.push(Violation { round: Some(round_index), player, rule });
+    let n_cards = round.n_cards;
+
+    if n_cards > MAX_CARDS {
+        round_violation(None, Rule::TooManyCards { n_cards });
+    }
+    if n_cards as usize * n_players > DECK_SIZE {
+        round_violation(None, Rule::NotEnoughCards { n_cards });
+    }
+    if round.trump > MAX_TRUMP {
+        round_violation(None, Rule::InvalidTrump { trump: round.trump });
+    }
+
+    if round.dealer_id as usize >= n_players {
+        round_violation(None, Rule::InvalidDealer { dealer_id: round.dealer_id });
+    } else if let Some(previous) = previous_dealer {
+        let expected = ((previous as usize + 1) % n_players) as u8;
+        if round.dealer_id != expected {
+            round_violation(None, Rule::DealerOutOfTurn { dealer_id: round.dealer_id, expected });
+        }
+    }
+
+    if round.bids.len() > n_players {
+        round_violation(None, Rule::WrongNumberOfBids { n_bids: round.bids.len() });
+    }
+    if round.tricks.len() > n_players {
+        round_violation(None, Rule::WrongNumberOfTricks { n_tricks: round.tricks.len() });
+    }
+    if round.tricks.len() == n_players && round.tricks.iter().all(Option::is_some) {
+        let total = round.tricks.iter().flatten().map(|&t| t as usize).sum();
+        if total != n_cards as usize {
+            round_violation(None, Rule::TricksDoNotAddUp { total, n_cards });
+        }
+    }
+
+    for (player, bid) in round.bids.iter().enumerate() {
+        if let Some(bid) = *bid {
+            if bid > n_cards {
+                round_violation(Some(player as u8), Rule::BidTooHigh { bid, n_cards });
+            }
+        }
+    }
+    for (player, tricks) in round.tricks.iter().enumerate() {
+        if let Some(tricks) = *tricks {
+            if tricks > n_cards {
+                round_violation(Some(player as u8), Rule::TricksTooHigh { tricks, n_cards });
+            }
+            if round.bids.get(player).copied().flatten().is_none() {
+                round_violation(Some(player as u8), Rule::TricksWithoutBids);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::game;
+    use crate::{encode, JsGame, JsRound, SerializeError};
+    use crate::validate::{validate_game, Rule, Violation};
+
+    fn test_game() -> JsGame {
+        game(&["Player 1", "Player 2", "Player 3"], vec![
+            JsRound { n_cards: 10, trump: 0, bids: vec![Some(2), Some(3), Some(4)], tricks: vec![Some(2), Some(3), Some(5)], dealer_id: 2 },
+            JsRound { n_cards: 9, trump: 4, bids: vec![Some(1), Some(0), Some(3)], tricks: vec![], dealer_id: 0 },
+            JsRound { n_cards: 8, trump: 2, bids: vec![], tricks: vec![], dealer_id: 1 },
+        ])
+    }
+
+    #[test]
+    fn test_valid_game() {
+        assert_eq!(Vec::<Violation>::new(), validate_game(&test_game()));
+    }
+
+    #[test]
+    fn test_player_violations() {
+        let mut game = test_game();
+        game.rounds[1].bids[2] = Some(11);
+        game.rounds[0].tricks = vec![Some(0), Some(11), Some(0)];
+
+        let violations = validate_game(&game);
+        assert_eq!(3, violations.len());
+        assert!(violations.contains(&Violation { round: Some(1), player: Some(2), rule: Rule::BidTooHigh { bid: 11, n_cards: 9 } }));
+        assert!(violations.contains(&Violation { round: Some(0), player: Some(1), rule: Rule::TricksTooHigh { tricks: 11, n_cards: 10 } }));
+        assert!(violations.contains(&Violation { round: Some(0), player: None, rule: Rule::TricksDoNotAddUp { total: 11, n_cards: 10 } }));
+    }
+
+    #[test]
+    fn test_round_violations() {
+        let mut game = test_game();
+        game.rounds[0].dealer_id = 3;
+        game.rounds[1].trump = 7;
+        game.rounds[2].dealer_id = 2;
+        game.rounds[2].n_cards = 18;
+        game.rounds[2].tricks = vec![Some(6), None, Some(6)];
+
+        let rules: Vec<(Option<usize>, Rule)> = validate_game(&game).into_iter().map(|v| (v.round, v.rule)).collect();
+        assert_eq!(vec![
+            (Some(0), Rule::InvalidDealer { dealer_id: 3 }),
+            (Some(1), Rule::InvalidTrump { trump: 7 }),
+            (Some(1), Rule::DealerOutOfTurn { dealer_id: 0, expected: 1 }),
+            (Some(2), Rule::NotEnoughCards { n_cards: 18 }),
+            (Some(2), Rule::DealerOutOfTurn { dealer_id: 2, expected: 1 }),
+            (Some(2), Rule::TricksWithoutBids),
+            (Some(2), Rule::TricksWithoutBids),
+        ], rules);
+    }
+
+    #[test]
+    fn test_partially_entered_round() {
+        let mut game = test_game();
+        game.rounds[2].bids = vec![Some(3), None, Some(2)];
+        game.rounds[1].tricks = vec![None, Some(4)];
+        assert_eq!(Vec::<Violation>::new(), validate_game(&game));
+
+        game.rounds[2].bids.extend([Some(1), Some(1)]);
+        let rules: Vec<Rule> = validate_game(&game).into_iter().map(|v| v.rule).collect();
+        assert_eq!(vec![Rule::WrongNumberOfBids { n_bids: 5 }], rules);
+    }
+
+    #[test]
+    fn test_names_too_long() {
+        let mut game = test_game();
+        game.name = "x".repeat(256);
+        game.players[1].name = "é".repeat(128);
+        game.players[2].name = "x".repeat(255);
+
+        let violations = validate_game(&game);
+        assert_eq!(vec![
+            Violation { round: None, player: None, rule: Rule::NameTooLong { n_bytes: 256 } },
+            Violation { round: None, player: Some(1), rule: Rule::NameTooLong { n_bytes: 256 } },
+        ], violations);
+        assert_eq!("player 2: name is 256 bytes, at most 255 are supported", violations[1].to_string());
+        assert_eq!(Err(SerializeError::NameTooLong(256)), encode(&game));
+    }
+
+    #[test]
+    fn test_game_violations() {
+        let rules: Vec<Rule> = validate_game(&game(&[], vec![])).into_iter().map(|v| v.rule).collect();
+        assert_eq!(vec![Rule::NoPlayers, Rule::NoRounds], rules);
+    }
+}
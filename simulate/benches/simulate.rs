@@ -0,0 +1,67 @@
+//! Games per second of the simulation core, run with `cargo bench`. Uses no benchmark framework,
+//! every query is simulated for a fixed number of seeded games and timed a few times.
+//!
+//! `cargo bench --bench simulate -- --save-baseline <file>` stores the result, a later run with
+//! `--baseline <file>` shows the speedup over it per query. The bench only uses `Simulator::new`
+//! and `Simulator::step`, so it can be copied into an older checkout to store a baseline of that
+//! version.
+//!
+//! Games per second depend on the machine, so a baseline is only compared on the machine that
+//! stored it. Against b4f3f50^, the version before cards became bitsets, built with the release
+//! profile of this crate and measured on one core of the same machine (best of three runs):
+//!
+//! ```text
+//! query                                         before     after  speedup
+//! 4p h 2s 4s AH                                  71891    449195     6.2x
+//! 4p h 2s 4s AH vs=bid                           45105    355459     7.9x
+//! 5p c 2c 3c 10d Kd As Qh 5h 7s 9c vs=classic    16161    119697     7.4x
+//! 5p c 2c 3c 10d Kd As Qh 5h 7s 9c vs=bid         7138     65845     9.2x
+//! 3p x KD QS 2C vs=lowest seat=2 bids=1         256318   1751577     6.8x
+//! ```
+
+use std::collections::HashMap;
+use std::time::Instant;
+use simulate::simulator::Simulator;
+
+const N_GAMES: usize = 20_000;
+const N_RUNS: usize = 5;
+
+fn main() {
+    // cargo passes --bench, other arguments come after --
+    let args: Vec<String> = std::env::args().collect();
+    let arg = |name: &str| args.iter().position(|arg| arg == name).and_then(|index| args.get(index + 1));
+    let baseline: HashMap<String, f64> = match arg("--baseline") {
+        Some(path) => std::fs::read_to_string(path).expect("a readable baseline").lines()
+            .filter_map(|line| line.rsplit_once('\t'))
+            .map(|(query, games)| (query.to_string(), games.parse().expect("games per second")))
+            .collect(),
+        None => HashMap::new(),
+    };
+
+    let queries = [
+        "4p h 2s 4s AH",
+        "4p h 2s 4s AH vs=bid",
+        "5p c 2c 3c 10d Kd As Qh 5h 7s 9c vs=classic",
+        "5p c 2c 3c 10d Kd As Qh 5h 7s 9c vs=bid",
+        "3p x KD QS 2C vs=lowest seat=2 bids=1",
+    ];
+    let mut results = String::new();
+    for query in queries {
+        let best = (0..N_RUNS).map(|run| {
+            let mut simulator = Simulator::new(query.to_string(), Some(run as u64)).unwrap();
+            let start = Instant::now();
+            simulator.step(N_GAMES).unwrap();
+            start.elapsed().as_secs_f64()
+        }).fold(f64::INFINITY, f64::min);
+        let games = N_GAMES as f64 / best;
+        match baseline.get(query) {
+            Some(base) => println!("{:<48} {:>10.0} games/s {:>6.1}x baseline", query, games, games / base),
+            None => println!("{:<48} {:>10.0} games/s", query, games),
+        }
+        results.push_str(&format!("{}\t{:.0}\n", query, games));
+    }
+
+    if let Some(path) = arg("--save-baseline") {
+        std::fs::write(path, results).expect("a writable baseline");
+    }
+}
@@ -1,6 +1,8 @@
 use std::fmt::Display;
 use std::str::FromStr;
 use colored::*;
+use rand::Rng;
+use crate::simulation::SimulateError;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Hash)]
 pub enum Suit {
@@ -11,6 +13,9 @@ pub enum Suit {
 }
 
 impl Suit {
+    /// Every suit, in the order of the cards in a sorted deck.
+    pub const ALL: [Suit; 4] = [Suit::Spades, Suit::Hearts, Suit::Diamonds, Suit::Clubs];
+
     pub fn from_char(c: &str) -> Option<Self> {
         match c.to_lowercase().as_str() {
             "s" => Some(Suit::Spades),
@@ -59,40 +64,91 @@ pub enum Rank {
     Ace,
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
-pub struct Card {
-    pub suit: Suit,
-    pub rank: Rank,
+impl Rank {
+    /// Every rank, from low to high.
+    pub const ALL: [Rank; 13] = [
+        Rank::Two,
+        Rank::Three,
+        Rank::Four,
+        Rank::Five,
+        Rank::Six,
+        Rank::Seven,
+        Rank::Eight,
+        Rank::Nine,
+        Rank::Ten,
+        Rank::Jack,
+        Rank::Queen,
+        Rank::King,
+        Rank::Ace,
+    ];
 }
 
-impl Rank {
-    pub fn from_str(s: &str) -> Option<Self> {
+impl FromStr for Rank {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_uppercase().as_str() {
-            "2" => Some(Rank::Two),
-            "3" => Some(Rank::Three),
-            "4" => Some(Rank::Four),
-            "5" => Some(Rank::Five),
-            "6" => Some(Rank::Six),
-            "7" => Some(Rank::Seven),
-            "8" => Some(Rank::Eight),
-            "9" => Some(Rank::Nine),
-            "10" => Some(Rank::Ten),
-            "J" => Some(Rank::Jack),
-            "Q" => Some(Rank::Queen),
-            "K" => Some(Rank::King),
-            "A" => Some(Rank::Ace),
-            _ => None,
+            "2" => Ok(Rank::Two),
+            "3" => Ok(Rank::Three),
+            "4" => Ok(Rank::Four),
+            "5" => Ok(Rank::Five),
+            "6" => Ok(Rank::Six),
+            "7" => Ok(Rank::Seven),
+            "8" => Ok(Rank::Eight),
+            "9" => Ok(Rank::Nine),
+            "10" => Ok(Rank::Ten),
+            "J" => Ok(Rank::Jack),
+            "Q" => Ok(Rank::Queen),
+            "K" => Ok(Rank::King),
+            "A" => Ok(Rank::Ace),
+            _ => Err(()),
         }
     }
 }
 
-impl From<&str> for Card {
-    fn from(s: &str) -> Self {
-        Card::from_string(s).unwrap()
+/// A card as its index in a sorted deck: the suits in the order of `Suit::ALL`, every suit from
+/// two to ace. Cards order by suit first and then by rank.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Hash)]
+pub struct Card(u8);
+
+impl FromStr for Card {
+    type Err = SimulateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Card::from_string(s).ok_or_else(|| SimulateError::UnknownCard { token: s.to_string() })
     }
 }
 
+/// The card of a string like "10h", for tests.
+#[cfg(test)]
+pub(crate) fn card(s: &str) -> Card {
+    s.parse().unwrap()
+}
+
 impl Card {
+    pub fn new(suit: Suit, rank: Rank) -> Self {
+        Card(suit as u8 * 13 + rank as u8)
+    }
+
+    /// The card with index `index` in a sorted deck, see `Card::index`.
+    pub fn from_index(index: usize) -> Self {
+        debug_assert!(index < 52);
+        Card(index as u8)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn suit(self) -> Suit {
+        // the index is below 52, the mask only spares the bounds check
+        Suit::ALL[(self.0 as usize / 13) & 3]
+    }
+
+    pub fn rank(self) -> Rank {
+        Rank::ALL[self.0 as usize % 13]
+    }
+
     pub fn from_string(s: &str) -> Option<Self> {
         if s.len() < 2 {
             return None;
@@ -102,13 +158,13 @@ impl Card {
         let (rank_str, suit_char) = s.split_at(s.len() - 1);
         let suit = Suit::from_char(suit_char)?;
 
-        let rank = Rank::from_str(rank_str)?;
+        let rank = rank_str.parse().ok()?;
 
-        Some(Card { rank, suit })
+        Some(Card::new(suit, rank))
     }
 
-    pub fn to_string(&self) -> String {
-        let rank_str = match self.rank {
+    fn rank_str(self) -> &'static str {
+        match self.rank() {
             Rank::Two => "2",
             Rank::Three => "3",
             Rank::Four => "4",
@@ -122,76 +178,440 @@ impl Card {
             Rank::Queen => "Q",
             Rank::King => "K",
             Rank::Ace => "A",
-        };
-
-        format!("{}{}", rank_str, self.suit)
+        }
     }
 }
 
-
 impl Display for Card {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.to_string())
-    }
-}
-
-pub fn create_deck(without: &Vec<Card>) -> Vec<Card> {
-    let mut cards = Vec::with_capacity(52 - without.len());
-    for suit in [Suit::Spades, Suit::Hearts, Suit::Diamonds, Suit::Clubs].iter() {
-        for rank in [
-            Rank::Two,
-            Rank::Three,
-            Rank::Four,
-            Rank::Five,
-            Rank::Six,
-            Rank::Seven,
-            Rank::Eight,
-            Rank::Nine,
-            Rank::Ten,
-            Rank::Jack,
-            Rank::Queen,
-            Rank::King,
-            Rank::Ace,
-        ]
-            .iter()
-        {
-            let card = Card {
-                suit: *suit,
-                rank: *rank,
-            };
-
-            if without.contains(&card) {
-                continue;
+        write!(f, "{}{}", self.rank_str(), self.suit())
+    }
+}
+
+// like the query syntax, e.g. 10h, without the colors of `Display`
+impl std::fmt::Debug for Card {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let suit = ["s", "h", "d", "c"][self.0 as usize / 13];
+        write!(f, "{}{}", self.rank_str(), suit)
+    }
+}
+
+/// A set of cards as a 52-bit mask, bit i is the card with index i.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Hash)]
+pub struct CardSet(u64);
+
+impl CardSet {
+    pub const EMPTY: CardSet = CardSet(0);
+    pub const DECK: CardSet = CardSet((1 << 52) - 1);
+
+    pub fn of_suit(suit: Suit) -> Self {
+        CardSet(0x1fff << (suit as u64 * 13))
+    }
+
+    pub fn of_rank(rank: Rank) -> Self {
+        CardSet(0x80_0400_2001 << rank as u64)
+    }
+
+    /// The cards of the same suit as `card` that rank above it.
+    pub fn above(card: Card) -> Self {
+        CardSet(Self::of_suit(card.suit()).0 & !((2 << card.0) - 1))
+    }
+
+    // bit r is set when the set holds a card of rank r in any suit
+    fn ranks(self) -> u64 {
+        (self.0 | self.0 >> 13 | self.0 >> 26 | self.0 >> 39) & 0x1fff
+    }
+
+    /// The highest rank in the set, whatever the suit.
+    pub fn highest_rank(self) -> Option<Rank> {
+        let ranks = self.ranks();
+        (ranks != 0).then(|| Rank::ALL[63 - ranks.leading_zeros() as usize])
+    }
+
+    /// The lowest rank in the set, whatever the suit.
+    pub fn lowest_rank(self) -> Option<Rank> {
+        let ranks = self.ranks();
+        (ranks != 0).then(|| Rank::ALL[ranks.trailing_zeros() as usize])
+    }
+
+    /// The card with the lowest index, the lowest rank of the first suit.
+    pub fn first(self) -> Option<Card> {
+        (self.0 != 0).then(|| Card(self.0.trailing_zeros() as u8))
+    }
+
+    /// The card with the highest index, the highest rank of the last suit.
+    pub fn last(self) -> Option<Card> {
+        (self.0 != 0).then(|| Card(63 - self.0.leading_zeros() as u8))
+    }
+
+    pub fn contains(self, card: Card) -> bool {
+        self.0 & (1 << card.0) != 0
+    }
+
+    pub fn insert(&mut self, card: Card) {
+        self.0 |= 1 << card.0;
+    }
+
+    pub fn remove(&mut self, card: Card) {
+        self.0 &= !(1 << card.0);
+    }
+
+    pub fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn union(self, other: CardSet) -> Self {
+        CardSet(self.0 | other.0)
+    }
+
+    pub fn intersection(self, other: CardSet) -> Self {
+        CardSet(self.0 & other.0)
+    }
+
+    pub fn difference(self, other: CardSet) -> Self {
+        CardSet(self.0 & !other.0)
+    }
+
+    /// The cards from low to high, in the order of a sorted deck.
+    pub fn iter(self) -> impl Iterator<Item = Card> {
+        let mut bits = self.0;
+        std::iter::from_fn(move || {
+            let card = CardSet(bits).first()?;
+            bits &= bits - 1;
+            Some(card)
+        })
+    }
+}
+
+impl From<Card> for CardSet {
+    fn from(card: Card) -> Self {
+        CardSet(1 << card.0)
+    }
+}
+
+impl FromIterator<Card> for CardSet {
+    fn from_iter<I: IntoIterator<Item = Card>>(iter: I) -> Self {
+        let mut set = CardSet::EMPTY;
+        for card in iter {
+            set.insert(card);
+        }
+        set
+    }
+}
+
+impl<'a> FromIterator<&'a Card> for CardSet {
+    fn from_iter<I: IntoIterator<Item = &'a Card>>(iter: I) -> Self {
+        iter.into_iter().copied().collect()
+    }
+}
+
+/// The largest hand, when the deck is dealt to two players.
+pub const MAX_HAND: usize = 26;
+
+/// Cards in hand in the order the player considers them, see `strategy::Classic`, together with
+/// the same cards as a set for quick lookups. A hand lives on the stack, playing a card does not
+/// allocate. A played card leaves an empty slot, so the cards after it keep their index.
+#[derive(Clone, Copy)]
+pub struct Hand {
+    cards: [Card; MAX_HAND],
+    // bit i is set when slot i holds a card
+    slots: u32,
+    len: u8,
+    // the slots from here on never held a card
+    end: u8,
+    // the slots of every suit, including those of played cards
+    suit_slots: [u32; 4],
+    set: CardSet,
+}
+
+impl Hand {
+    pub fn set(&self) -> CardSet {
+        self.set
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots == 0
+    }
+
+    /// Add a card after the last one.
+    pub fn push(&mut self, card: Card) {
+        let index = self.end as usize;
+        self.cards[index] = card;
+        self.slots |= 1 << index;
+        self.len += 1;
+        self.end += 1;
+        self.suit_slots[card.suit() as usize] |= 1 << index;
+        self.set.insert(card);
+    }
+
+    /// Put the cards in a random order, every order equally likely.
+    pub fn shuffle<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let len = self.len();
+        if self.slots != (1 << len) - 1 {
+            // close the empty slots first
+            for (to, from) in self.indices().enumerate() {
+                self.cards[to] = self.cards[from];
             }
+            self.slots = (1 << len) - 1;
+            self.end = len as u8;
+        }
+        shuffle_last(&mut self.cards[..len], len, rng);
+        // the slots of the four suits side by side in one number
+        let mut suit_slots = 0u128;
+        for (index, card) in self.cards[..len].iter().enumerate() {
+            suit_slots |= 1 << (card.suit() as usize * 32 + index);
+        }
+        self.suit_slots = std::array::from_fn(|suit| (suit_slots >> (32 * suit)) as u32);
+    }
+
+    /// The cards in hand in order.
+    pub fn iter(&self) -> impl Iterator<Item = Card> + Clone + '_ {
+        self.indices().map(|index| self.cards[index])
+    }
+
+    /// The indices of the cards in hand in order, see `Hand::get`.
+    pub fn indices(&self) -> impl Iterator<Item = usize> + Clone {
+        let mut slots = self.slots;
+        std::iter::from_fn(move || {
+            let index = (slots != 0).then(|| slots.trailing_zeros() as usize)?;
+            slots &= slots - 1;
+            Some(index)
+        })
+    }
 
-            cards.push(card);
+    /// The card at `index`, which holds a card.
+    pub fn get(&self, index: usize) -> Card {
+        debug_assert!(self.slots & 1 << index != 0);
+        self.cards[index]
+    }
+
+    /// Index of the first card in hand that is in `cards`.
+    pub fn first_of(&self, cards: CardSet) -> Option<usize> {
+        let slots = self.slots_of(cards);
+        (slots != 0).then(|| slots.trailing_zeros() as usize)
+    }
+
+    /// Index of the last card in hand that is in `cards`.
+    pub fn last_of(&self, cards: CardSet) -> Option<usize> {
+        let slots = self.slots_of(cards);
+        (slots != 0).then(|| 31 - slots.leading_zeros() as usize)
+    }
+
+    // the slots that hold a card of `cards`. All slots up to `end` are looked at, a number that
+    // does not change while the cards are played, so unlike stopping at the first match the loop
+    // has no branch that is hard to predict
+    fn slots_of(&self, cards: CardSet) -> u32 {
+        let mut slots = 0;
+        for (index, card) in self.cards[..self.end as usize].iter().enumerate() {
+            slots |= ((cards.0 >> card.0) as u32 & 1) << index;
         }
+        slots & self.slots
+    }
+
+    /// Index of the first card in hand of `suit`.
+    pub fn first_of_suit(&self, suit: Suit) -> Option<usize> {
+        let slots = self.suit_slots[suit as usize] & self.slots;
+        (slots != 0).then(|| slots.trailing_zeros() as usize)
+    }
+
+    /// Index of the first card in hand.
+    pub fn first(&self) -> Option<usize> {
+        (self.slots != 0).then(|| self.slots.trailing_zeros() as usize)
+    }
+
+    /// Index of the last card in hand.
+    pub fn last(&self) -> Option<usize> {
+        (self.slots != 0).then(|| 31 - self.slots.leading_zeros() as usize)
+    }
+
+    /// Take out the card at `index`, the other cards keep their index.
+    pub fn remove(&mut self, index: usize) -> Card {
+        let card = self.get(index);
+        self.slots &= !(1 << index);
+        self.len -= 1;
+        self.set.remove(card);
+        card
+    }
+}
+
+impl Default for Hand {
+    fn default() -> Self {
+        Hand { cards: [Card(0); MAX_HAND], slots: 0, len: 0, end: 0, suit_slots: [0; 4], set: CardSet::EMPTY }
     }
-    cards
+}
+
+impl From<&[Card]> for Hand {
+    fn from(cards: &[Card]) -> Self {
+        let mut hand = Hand::default();
+        for &card in cards {
+            hand.push(card);
+        }
+        hand
+    }
+}
+
+impl std::fmt::Debug for Hand {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// batches of random indices are drawn from a range of at most 2^48 numbers, so a batch is drawn
+// again with a chance below 2^-16
+const MAX_BATCH_RANGE: u64 = 1 << 48;
+
+/// Put `amount` random cards at the end of `cards` in random order, like
+/// `SliceRandom::partial_shuffle`, with every order equally likely. Shuffles all cards when
+/// `amount` is their number.
+///
+/// A Fisher-Yates shuffle needs a random index for every swap, which are taken in batches from
+/// one random number: an index below the product of the bounds of the batch is drawn with
+/// Lemire's method, and its digits in mixed radix are the indices.
+pub fn shuffle_last<R: Rng + ?Sized>(cards: &mut [Card], amount: usize, rng: &mut R) {
+    let end = cards.len() - amount;
+    // the card swapped into position i comes from 0..=i
+    let mut i = cards.len();
+    while i > end.max(1) {
+        let mut range = 1;
+        let mut first = i;
+        while first > end.max(1) && range * first as u64 <= MAX_BATCH_RANGE {
+            range *= first as u64;
+            first -= 1;
+        }
+
+        // the low bits of x * range decide if drawing x favours some indices
+        let mut bits = loop {
+            let x = rng.next_u64();
+            let low = x.wrapping_mul(range);
+            if low >= range || low >= range.wrapping_neg() % range {
+                break x;
+            }
+        };
+        for bound in (first + 1..=i).rev() {
+            let product = bits as u128 * bound as u128;
+            cards.swap(bound - 1, (product >> 64) as usize);
+            bits = product as u64;
+        }
+        i = first;
+    }
+}
+
+pub fn create_deck(without: &[Card]) -> Vec<Card> {
+    CardSet::DECK.difference(without.iter().collect()).iter().collect()
 }
 
 // return the highest ranked card in a list of cards with optional trump suit
 // if no the highest rank of the first suit in the list is returned
-pub fn highest_card(cards: &Vec<Card>, trump: Option<Suit>) -> Option<(usize, &Card)> {
+pub fn highest_card(cards: &[Card], trump: Option<Suit>) -> Option<(usize, &Card)> {
     if cards.is_empty() {
         return None;
     }
 
-    let winning_suit = match trump {
-        Some(suit) if {cards.iter().any(|c| c.suit == suit )} => suit,
-        _ => cards.first()?.suit,
-    };
+    let mut winner = (0, &cards[0]);
+    for (index, card) in cards.iter().enumerate().skip(1) {
+        if beats(*card, *winner.1, trump) {
+            winner = (index, card);
+        }
+    }
+    Some(winner)
+}
 
-    let winner = cards
-        .iter()
-        .enumerate()
-        .filter(|(_idx, card)| {
-            card.suit == winning_suit
-        })
-        .max_by_key(|&(_,item)| item);
-    winner
+/// Whether `card` takes the trick from `best`, the card that wins it so far: with a higher card
+/// of the same suit or the first trump.
+pub fn beats(card: Card, best: Card, trump: Option<Suit>) -> bool {
+    match card.suit() == best.suit() {
+        true => card > best,
+        false => Some(card.suit()) == trump,
+    }
+}
+
+// None if any of the cards is invalid, see `query::parse_query` for errors with positions
+pub fn create_hand_from_string(s: &str) -> Option<Vec<Card>> {
+    s.split_whitespace().map(Card::from_string).collect()
 }
 
-pub fn create_hand_from_string(s: &str) -> Vec<Card> {
-    s.split_whitespace().map(|s| Card::from_string(s).unwrap()).collect()
-}
\ No newline at end of file
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use rand::SeedableRng;
+    use crate::card::{card, create_deck, create_hand_from_string, shuffle_last, Card, CardSet, Hand, Rank, Suit};
+    use crate::simulation::{SimulateError, SimulationRng};
+
+    fn set(s: &str) -> CardSet {
+        create_hand_from_string(s).unwrap().iter().collect()
+    }
+
+    #[test]
+    fn test_card_index() {
+        for index in 0..52 {
+            let card = Card::from_index(index);
+            assert_eq!(card, Card::new(card.suit(), card.rank()));
+            assert_eq!(index, card.index());
+        }
+        assert_eq!((Suit::Hearts, Rank::Ten), (card("10h").suit(), card("10h").rank()));
+        assert!(card("As") < card("2h"));
+        assert_eq!(Err(SimulateError::UnknownCard { token: "1s".to_string() }), "1s".parse::<Card>());
+    }
+
+    #[test]
+    fn test_card_set() {
+        let cards = set("2s Ks 3h Ad 10d");
+        assert_eq!(5, cards.len());
+        assert_eq!(set("2s Ks"), cards.intersection(CardSet::of_suit(Suit::Spades)));
+        assert_eq!(set("Ks Ad"), cards.intersection(CardSet::above(card("Js")).union(CardSet::above(card("10d")))));
+        assert_eq!(set("3h"), cards.intersection(CardSet::of_rank(Rank::Three)));
+        assert_eq!((Some(Rank::Ace), Some(Rank::Two)), (cards.highest_rank(), cards.lowest_rank()));
+        assert_eq!((Some(card("2s")), Some(card("Ad"))), (cards.first(), cards.last()));
+        assert_eq!(create_hand_from_string("2s Ks 3h 10d Ad").unwrap(), cards.iter().collect::<Vec<_>>());
+        assert_eq!(47, CardSet::DECK.difference(cards).len());
+        assert_eq!(None, CardSet::EMPTY.highest_rank());
+    }
+
+    #[test]
+    fn test_hand() {
+        let mut hand = Hand::from(&create_hand_from_string("Ks 2s 3h Ad").unwrap()[..]);
+        assert_eq!((Some(0), Some(1)), (hand.first_of(CardSet::of_suit(Suit::Spades)), hand.last_of(CardSet::of_suit(Suit::Spades))));
+        assert_eq!(card("2s"), hand.remove(1));
+        assert_eq!(create_hand_from_string("Ks 3h Ad").unwrap(), hand.iter().collect::<Vec<_>>());
+        assert_eq!(set("Ks 3h Ad"), hand.set());
+        assert_eq!(None, hand.first_of(set("2s")));
+        // the cards after a played one keep their index
+        assert_eq!((3, Some(2), Some(3)), (hand.len(), hand.first_of_suit(Suit::Hearts), hand.last()));
+        assert_eq!(card("3h"), hand.get(2));
+
+        hand.shuffle(&mut SimulationRng::seed_from_u64(1));
+        assert_eq!((3, set("Ks 3h Ad")), (hand.len(), hand.iter().collect()));
+        let hearts = hand.first_of_suit(Suit::Hearts).unwrap();
+        assert_eq!((card("3h"), Some(hearts)), (hand.get(hearts), hand.first_of(set("3h"))));
+    }
+
+    #[test]
+    fn test_shuffle_last() {
+        let mut rng = SimulationRng::seed_from_u64(4);
+        let deck: Vec<Card> = create_hand_from_string("2s 3s 4s 5s").unwrap();
+        // every order of all cards, and every pair of cards at the end, is equally likely
+        for (amount, n_outcomes) in [(4, 24), (2, 12)] {
+            let mut counts = HashMap::new();
+            for _ in 0..60_000 {
+                let mut cards = deck.clone();
+                shuffle_last(&mut cards, amount, &mut rng);
+                *counts.entry(cards[4 - amount..].to_vec()).or_insert(0) += 1;
+            }
+            assert_eq!(n_outcomes, counts.len());
+            assert!(counts.values().all(|&count| (count as f64 / 60_000.0 - 1.0 / n_outcomes as f64).abs() < 0.01), "{:?}", counts);
+        }
+
+        // more cards than one batch of random indices covers
+        let mut cards = create_deck(&[]);
+        shuffle_last(&mut cards, 52, &mut rng);
+        assert_eq!(CardSet::DECK, cards.iter().collect());
+    }
+}
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use crate::card::{shuffle_last, Card, CardSet, Hand, Suit};
+
+/// What is known about the hand of another player, e.g. from them not following suit.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constraint {
+    Void(Suit),
+    Holds(Card),
+    MinTrumps(usize),
+}
+
+/// Plain shuffles tried before a deal is drawn suit by suit, see `Dealer`.
+pub const MAX_DEAL_ATTEMPTS: usize = 20;
+
+// n! for the number of cards of one suit
+const FACTORIALS: [f64; 14] = {
+    let mut factorials = [1.0; 14];
+    let mut n = 1;
+    while n < 14 {
+        factorials[n] = factorials[n - 1] * n as f64;
+        n += 1;
+    }
+    factorials
+};
+
+// how many cards of every suit a player with voids or a minimum of trumps may still be dealt
+#[derive(Debug, Clone)]
+struct Limits {
+    player: usize,
+    need: usize,
+    min: [usize; 4],
+    max: [usize; 4],
+}
+
+/// Deals the cards nobody has seen, uniformly over every deal that meets the constraints.
+/// Constraints are given as (index in `hand_sizes`, constraint).
+///
+/// Held cards are placed first. A deal is then tried as a plain shuffle of the other cards and
+/// kept when it meets the constraints. After `MAX_DEAL_ATTEMPTS` failed shuffles the deal is
+/// drawn suit by suit instead: the number of cards of a suit each constrained player gets is
+/// drawn with the number of deals that have those numbers as weight, the cards themselves are
+/// shuffled. Either way every consistent deal is equally likely, so mixing them keeps it so.
+/// The cards in every hand are in random order.
+#[derive(Debug, Clone)]
+pub struct Dealer {
+    // unseen cards without the held ones, sorted
+    deck: Vec<Card>,
+    hand_sizes: Vec<usize>,
+    held: Vec<Vec<Card>>,
+    // cards dealt besides the held ones
+    needed: usize,
+    constraints: Vec<(usize, Constraint)>,
+    trump: Option<Suit>,
+    limits: Vec<Limits>,
+    // number of ways to deal the suits from an index on with the constrained players still
+    // needing the given cards, up to a factor that is the same for every deal
+    ways: HashMap<(usize, Vec<usize>), f64>,
+}
+
+impl Dealer {
+    /// None when no deal meets the constraints.
+    pub fn new(deck: &[Card], hand_sizes: &[usize], constraints: &[(usize, Constraint)], trump: Option<Suit>) -> Option<Self> {
+        let mut unseen: CardSet = deck.iter().collect();
+        let mut held = vec![Vec::new(); hand_sizes.len()];
+        for (player, constraint) in constraints {
+            if let Constraint::Holds(card) = constraint {
+                if !unseen.contains(*card) {
+                    return None;
+                }
+                unseen.remove(*card);
+                held[*player].push(*card);
+            }
+        }
+
+        let mut limits = Vec::new();
+        for (player, &hand_size) in hand_sizes.iter().enumerate() {
+            let need = hand_size.checked_sub(held[player].len())?;
+            let mut limit = Limits { player, need, min: [0; 4], max: [need; 4] };
+            let mut constrained = false;
+            for (_, constraint) in constraints.iter().filter(|(p, _)| *p == player) {
+                match *constraint {
+                    Constraint::Void(suit) => {
+                        if held[player].iter().any(|card| card.suit() == suit) {
+                            return None;
+                        }
+                        limit.max[suit as usize] = 0;
+                        constrained = true;
+                    }
+                    Constraint::MinTrumps(n) => {
+                        let Some(trump) = trump else {
+                            if n > 0 {
+                                return None;
+                            }
+                            continue;
+                        };
+                        let held_trumps = held[player].iter().filter(|card| card.suit() == trump).count();
+                        let min = &mut limit.min[trump as usize];
+                        *min = (*min).max(n.saturating_sub(held_trumps));
+                        constrained = true;
+                    }
+                    Constraint::Holds(_) => {}
+                }
+            }
+            if constrained {
+                limits.push(limit);
+            }
+        }
+
+        let needed: usize = hand_sizes.iter().zip(&held).map(|(&size, held)| size - held.len()).sum();
+        if unseen.len() < needed {
+            return None;
+        }
+
+        let mut dealer = Dealer {
+            deck: unseen.iter().collect(),
+            hand_sizes: hand_sizes.to_vec(),
+            held,
+            needed,
+            constraints: constraints.iter().filter(|(_, constraint)| !matches!(constraint, Constraint::Holds(_))).cloned().collect(),
+            trump,
+            limits,
+            ways: HashMap::new(),
+        };
+        let needs: Vec<usize> = dealer.limits.iter().map(|limit| limit.need).collect();
+        if dealer.count_ways(0, needs) == 0.0 {
+            return None;
+        }
+        Some(dealer)
+    }
+
+    /// Deal every hand, with the held cards.
+    pub fn deal<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<Hand> {
+        let mut hands = vec![Hand::default(); self.n_players()];
+        self.deal_into(&mut hands, rng);
+        hands
+    }
+
+    /// `deal` into `hands`, one for every player, without allocating.
+    pub fn deal_into<R: Rng + ?Sized>(&self, hands: &mut [Hand], rng: &mut R) {
+        for _ in 0..MAX_DEAL_ATTEMPTS {
+            self.deal_shuffled(hands, rng);
+            if self.constraints.is_empty() || self.meets_constraints(hands) {
+                for (hand, held) in hands.iter_mut().zip(&self.held) {
+                    // the held cards were placed first
+                    if !held.is_empty() {
+                        hand.shuffle(rng);
+                    }
+                }
+                return;
+            }
+        }
+        hands.copy_from_slice(&self.deal_by_suit(rng));
+    }
+
+    /// The number of hands a deal has.
+    pub fn n_players(&self) -> usize {
+        self.hand_sizes.len()
+    }
+
+    fn deal_shuffled<R: Rng + ?Sized>(&self, hands: &mut [Hand], rng: &mut R) {
+        // the deck lives on the stack, hands are dealt from the end and only the cards that are
+        // dealt are shuffled
+        let mut deck = [Card::from_index(0); 52];
+        let mut end = self.deck.len();
+        deck[..end].copy_from_slice(&self.deck);
+        shuffle_last(&mut deck[..end], self.needed, rng);
+        for ((hand, &n), held) in hands.iter_mut().zip(&self.hand_sizes).zip(&self.held) {
+            *hand = Hand::from(&held[..]);
+            for _ in held.len()..n {
+                end -= 1;
+                hand.push(deck[end]);
+            }
+        }
+    }
+
+    fn meets_constraints(&self, hands: &[Hand]) -> bool {
+        self.constraints.iter().all(|(player, constraint)| {
+            let cards = hands[*player].set();
+            match *constraint {
+                Constraint::Void(suit) => cards.intersection(CardSet::of_suit(suit)).is_empty(),
+                Constraint::MinTrumps(n) => self.trump.is_some_and(|trump| cards.intersection(CardSet::of_suit(trump)).len() >= n),
+                Constraint::Holds(card) => cards.contains(card),
+            }
+        })
+    }
+
+    fn suit_size(&self, suit: usize) -> usize {
+        self.deck.iter().filter(|card| card.suit() as usize == suit).count()
+    }
+
+    // every way to give the constrained players cards of `suit`, as the number per player
+    fn splits(&self, suit: usize, needs: &[usize]) -> Vec<Vec<usize>> {
+        fn fill(limits: &[Limits], suit: usize, needs: &[usize], left: usize, split: &mut Vec<usize>, splits: &mut Vec<Vec<usize>>) {
+            let index = split.len();
+            if index == limits.len() {
+                splits.push(split.clone());
+                return;
+            }
+            let limit = &limits[index];
+            for n in limit.min[suit]..=limit.max[suit].min(needs[index]).min(left) {
+                split.push(n);
+                fill(limits, suit, needs, left - n, split, splits);
+                split.pop();
+            }
+        }
+
+        let mut splits = Vec::new();
+        fill(&self.limits, suit, needs, self.suit_size(suit), &mut Vec::with_capacity(needs.len()), &mut splits);
+        splits
+    }
+
+    // deals of one suit with this split, up to the factor n! of the suit size
+    fn split_weight(&self, suit: usize, split: &[usize]) -> f64 {
+        let rest = self.suit_size(suit) - split.iter().sum::<usize>();
+        1.0 / (split.iter().map(|&n| FACTORIALS[n]).product::<f64>() * FACTORIALS[rest])
+    }
+
+    fn count_ways(&mut self, suit: usize, needs: Vec<usize>) -> f64 {
+        if suit == Suit::ALL.len() {
+            return if needs.iter().all(|&n| n == 0) { 1.0 } else { 0.0 };
+        }
+        if let Some(&ways) = self.ways.get(&(suit, needs.clone())) {
+            return ways;
+        }
+
+        let mut ways = 0.0;
+        for split in self.splits(suit, &needs) {
+            let rest: Vec<usize> = needs.iter().zip(&split).map(|(need, n)| need - n).collect();
+            ways += self.split_weight(suit, &split) * self.count_ways(suit + 1, rest);
+        }
+        self.ways.insert((suit, needs), ways);
+        ways
+    }
+
+    fn ways(&self, suit: usize, needs: &[usize]) -> f64 {
+        if suit == Suit::ALL.len() {
+            return if needs.iter().all(|&n| n == 0) { 1.0 } else { 0.0 };
+        }
+        self.ways.get(&(suit, needs.to_vec())).copied().unwrap_or(0.0)
+    }
+
+    fn deal_by_suit<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<Hand> {
+        let mut hands: Vec<Hand> = self.held.iter().map(|held| Hand::from(&held[..])).collect();
+        let mut needs: Vec<usize> = self.limits.iter().map(|limit| limit.need).collect();
+        let mut rest = Vec::with_capacity(self.deck.len());
+
+        for suit in 0..Suit::ALL.len() {
+            let splits = self.splits(suit, &needs);
+            let weights: Vec<f64> = splits.iter().map(|split| {
+                let left: Vec<usize> = needs.iter().zip(split).map(|(need, n)| need - n).collect();
+                self.split_weight(suit, split) * self.ways(suit + 1, &left)
+            }).collect();
+            let split = &splits[WeightedIndex::new(&weights).expect("a dealer only exists for dealable constraints").sample(rng)];
+
+            let mut cards: Vec<Card> = self.deck.iter().copied().filter(|card| card.suit() as usize == suit).collect();
+            let n_cards = cards.len();
+            shuffle_last(&mut cards, n_cards, rng);
+            let mut cards = cards.into_iter();
+            for ((limit, need), &n) in self.limits.iter().zip(&mut needs).zip(split) {
+                for card in cards.by_ref().take(n) {
+                    hands[limit.player].push(card);
+                }
+                *need -= n;
+            }
+            rest.extend(cards);
+        }
+
+        // the players without limits share the rest, what is left is not dealt
+        let n_rest = rest.len();
+        shuffle_last(&mut rest, n_rest, rng);
+        let mut rest = rest.into_iter();
+        for (player, hand) in hands.iter_mut().enumerate() {
+            if !self.limits.iter().any(|limit| limit.player == player) {
+                for card in rest.by_ref().take(self.hand_sizes[player] - hand.len()) {
+                    hand.push(card);
+                }
+            }
+            hand.shuffle(rng);
+        }
+        hands
+    }
+}
+
+/// Whether every hand meets the constraints of its player.
+pub fn meets_constraints(hands: &[Vec<Card>], constraints: &[(usize, Constraint)], trump: Option<Suit>) -> bool {
+    constraints.iter().all(|(player, constraint)| {
+        let hand = &hands[*player];
+        match constraint {
+            Constraint::Void(suit) => hand.iter().all(|card| card.suit() != *suit),
+            Constraint::Holds(card) => hand.contains(card),
+            Constraint::MinTrumps(n) => hand.iter().filter(|card| Some(card.suit()) == trump).count() >= *n,
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use rand::SeedableRng;
+    use crate::card::{card, create_deck, Card, Hand, Suit};
+    use crate::deal::{meets_constraints, Constraint, Dealer};
+    use crate::simulation::SimulationRng;
+
+    fn to_vecs(hands: &[Hand]) -> Vec<Vec<Card>> {
+        hands.iter().map(|hand| hand.iter().collect::<Vec<_>>()).collect()
+    }
+
+    fn deal_many(constraints: &[(usize, Constraint)], check: impl Fn(&[Vec<Card>]) -> bool) {
+        let mut rng = SimulationRng::seed_from_u64(11);
+        let dealer = Dealer::new(&create_deck(&[]), &[0, 13, 13, 13], constraints, Some(Suit::Hearts)).unwrap();
+        for _ in 0..200 {
+            let hands = to_vecs(&dealer.deal(&mut rng));
+            assert!(hands.iter().skip(1).all(|hand| hand.len() == 13));
+            assert!(check(&hands));
+            assert!(meets_constraints(&hands, constraints, Some(Suit::Hearts)));
+        }
+    }
+
+    #[test]
+    fn test_void_and_holds() {
+        deal_many(&[(1, Constraint::Void(Suit::Spades)), (1, Constraint::Void(Suit::Clubs)), (2, Constraint::Holds(card("As")))], |hands| {
+            hands[1].iter().all(|card| card.suit() != Suit::Spades && card.suit() != Suit::Clubs) && hands[2].contains(&card("As"))
+        });
+    }
+
+    #[test]
+    fn test_min_trumps() {
+        deal_many(&[(3, Constraint::MinTrumps(6)), (3, Constraint::Holds(card("Ah")))], |hands| {
+            hands[3].iter().filter(|card| card.suit() == Suit::Hearts).count() >= 6
+        });
+    }
+
+    #[test]
+    fn test_impossible_deal() {
+        // only 26 spades and hearts for three players of 13 who hold no diamonds or clubs
+        let constraints: Vec<(usize, Constraint)> = [1, 2, 3].iter()
+            .flat_map(|&player| [(player, Constraint::Void(Suit::Diamonds)), (player, Constraint::Void(Suit::Clubs))])
+            .collect();
+        assert!(Dealer::new(&create_deck(&[]), &[0, 13, 13, 13], &constraints, Some(Suit::Hearts)).is_none());
+        assert!(Dealer::new(&create_deck(&[]), &[0, 2, 2], &[(1, Constraint::Void(Suit::Hearts)), (1, Constraint::Holds(card("2h")))], None).is_none());
+    }
+
+    // how often every deal comes up in `n` deals, as fractions
+    fn frequencies(n: usize, mut deal: impl FnMut() -> Vec<Hand>) -> HashMap<Vec<Vec<Card>>, f64> {
+        let mut counts = HashMap::new();
+        for _ in 0..n {
+            *counts.entry(to_vecs(&deal())).or_insert(0) += 1;
+        }
+        counts.into_iter().map(|(deal, count)| (deal, count as f64 / n as f64)).collect()
+    }
+
+    #[test]
+    fn test_uniform_over_consistent_deals() {
+        let mut rng = SimulationRng::seed_from_u64(5);
+        let deck: Vec<Card> = ["As", "Ah", "Ad"].into_iter().map(card).collect();
+        // player 1 is void in spades and player 2 in hearts, three deals are left
+        let constraints = [(1, Constraint::Void(Suit::Spades)), (2, Constraint::Void(Suit::Hearts))];
+        let dealer = Dealer::new(&deck, &[1, 1, 1], &constraints, None).unwrap();
+
+        for frequencies in [frequencies(30_000, || dealer.deal(&mut rng)), frequencies(30_000, || dealer.deal_by_suit(&mut rng))] {
+            assert_eq!(3, frequencies.len());
+            assert!(frequencies.values().all(|f| (f - 1.0 / 3.0).abs() < 0.01), "{:?}", frequencies);
+        }
+    }
+
+    #[test]
+    fn test_uniform_by_suit() {
+        let mut rng = SimulationRng::seed_from_u64(8);
+        // 6 cards over two hands of two, player 1 holds no spades and player 2 holds the Ah
+        let deck: Vec<Card> = ["As", "Ks", "Ah", "Kh", "Ad", "Kd"].into_iter().map(card).collect();
+        let constraints = [(1, Constraint::Void(Suit::Spades)), (2, Constraint::Holds(card("Ah")))];
+        let dealer = Dealer::new(&deck, &[0, 2, 2], &constraints, None).unwrap();
+        let sorted = |mut hands: Vec<Hand>| {
+            for hand in &mut hands {
+                let mut cards = hand.iter().collect::<Vec<_>>();
+                cards.sort();
+                *hand = Hand::from(&cards[..]);
+            }
+            hands
+        };
+
+        // player 1 gets 2 of Kh, Ad, Kd and player 2 one more of the 3 cards left: 3 * 3 deals
+        let frequencies = frequencies(45_000, || sorted(dealer.deal_by_suit(&mut rng)));
+        assert_eq!(9, frequencies.len());
+        assert!(frequencies.values().all(|f| (f - 1.0 / 9.0).abs() < 0.01), "{:?}", frequencies);
+    }
+}
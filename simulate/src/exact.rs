@@ -0,0 +1,261 @@
+use crate::card::{Card, Suit};
+use crate::deal::Constraint;
+use crate::game::RoundState;
+
+/// Games are enumerated instead of sampled when there are at most this many, counting every
+/// order of the cards in hand.
+pub const MAX_EXACT_GAMES: u64 = 1_000_000;
+
+fn binomial(n: usize, k: usize) -> Option<u64> {
+    (0..k as u64).try_fold(1u64, |result, i| Some(result.checked_mul(n as u64 - i)? / (i + 1)))
+}
+
+/// The number of ways to deal hands of `hand_sizes` cards from `n_unseen` cards, None when it
+/// does not fit in a u64.
+pub fn count_deals(n_unseen: usize, hand_sizes: &[usize]) -> Option<u64> {
+    let mut remaining = n_unseen;
+    let mut count: u64 = 1;
+    for &n in hand_sizes {
+        if n > remaining {
+            return Some(0);
+        }
+        count = count.checked_mul(binomial(remaining, n)?)?;
+        remaining -= n;
+    }
+    Some(count)
+}
+
+/// The number of orders the cards of hands of `hand_sizes` cards can be in, None when it does
+/// not fit in a u64.
+pub fn count_orders(hand_sizes: &[usize]) -> Option<u64> {
+    hand_sizes.iter().try_fold(1u64, |count, &n| (1..=n as u64).try_fold(count, |count, i| count.checked_mul(i)))
+}
+
+/// Every deal of hands of `hand_sizes` cards from `cards`, from the first to the last
+/// combination of the first hand. The cards of every hand keep their order in `cards`. The
+/// position is kept between deals, so the deals can be played a few at a time.
+#[derive(Debug, Clone)]
+pub struct Deals {
+    cards: Vec<Card>,
+    hand_sizes: Vec<usize>,
+    // per hand the indices of its cards in the cards the hands before it left over
+    indices: Vec<Vec<usize>>,
+    done: bool,
+}
+
+impl Deals {
+    pub fn new(cards: &[Card], hand_sizes: &[usize]) -> Self {
+        Deals {
+            cards: cards.to_vec(),
+            hand_sizes: hand_sizes.to_vec(),
+            indices: hand_sizes.iter().map(|&n| (0..n).collect()).collect(),
+            done: hand_sizes.iter().sum::<usize>() > cards.len(),
+        }
+    }
+
+    fn hands(&self) -> Vec<Vec<Card>> {
+        let mut remaining = self.cards.clone();
+        self.indices.iter().map(|indices| {
+            let hand: Vec<Card> = indices.iter().map(|&i| remaining[i]).collect();
+            let mut index = 0;
+            remaining.retain(|_| {
+                index += 1;
+                !indices.contains(&(index - 1))
+            });
+            hand
+        }).collect()
+    }
+}
+
+impl Iterator for Deals {
+    type Item = Vec<Vec<Card>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let hands = self.hands();
+
+        // the next combination of the last hand that has one, the hands after it start over
+        self.done = true;
+        let mut n_remaining = self.cards.len() - self.hand_sizes.iter().sum::<usize>();
+        for hand in (0..self.indices.len()).rev() {
+            n_remaining += self.hand_sizes[hand];
+            if next_combination(&mut self.indices[hand], n_remaining) {
+                for later in hand + 1..self.indices.len() {
+                    self.indices[later] = (0..self.hand_sizes[later]).collect();
+                }
+                self.done = false;
+                break;
+            }
+        }
+        Some(hands)
+    }
+}
+
+// the next combination of indices below `n` in lexicographic order, false after the last one
+fn next_combination(indices: &mut [usize], n: usize) -> bool {
+    let k = indices.len();
+    let mut i = k;
+    while i > 0 && indices[i - 1] == n - k + i - 1 {
+        i -= 1;
+    }
+    if i == 0 {
+        return false;
+    }
+    indices[i - 1] += 1;
+    for j in i..k {
+        indices[j] = indices[j - 1] + 1;
+    }
+    true
+}
+
+/// Call `f` with the hands in every order of the cards of the hands from `first` on, the hands
+/// before it keep their order. Those hands end up sorted.
+pub fn for_each_order(hands: &mut [Vec<Card>], first: usize, f: &mut dyn FnMut(&[Vec<Card>])) {
+    if first == hands.len() {
+        return f(hands);
+    }
+    hands[first].sort();
+    loop {
+        for_each_order(hands, first + 1, f);
+        if !next_permutation(&mut hands[first]) {
+            return;
+        }
+    }
+}
+
+// the next order in lexicographic order, or back to sorted and false after the last one
+fn next_permutation(cards: &mut [Card]) -> bool {
+    let Some(i) = cards.windows(2).rposition(|pair| pair[0] < pair[1]) else {
+        cards.reverse();
+        return false;
+    };
+    let j = cards.iter().rposition(|card| *card > cards[i]).unwrap();
+    cards.swap(i, j);
+    cards[i + 1..].reverse();
+    true
+}
+
+/// Suits that can be swapped with each other without changing the game: not trump, not in any
+/// known card and not named by a constraint.
+pub fn free_suits(trump: Option<Suit>, player_cards: &[Card], state: &RoundState) -> Vec<Suit> {
+    let constrained = state.constraints.iter().map(|(_, constraint)| match constraint {
+        Constraint::Void(suit) => Some(*suit),
+        Constraint::Holds(card) => Some(card.suit()),
+        Constraint::MinTrumps(_) => None,
+    });
+    let used: Vec<Suit> = player_cards.iter().chain(&state.played).chain(&state.trick)
+        .map(|card| Some(card.suit()))
+        .chain(constrained)
+        .chain([trump])
+        .flatten()
+        .collect();
+    [Suit::Spades, Suit::Hearts, Suit::Diamonds, Suit::Clubs].into_iter()
+        .filter(|suit| !used.contains(suit))
+        .collect()
+}
+
+/// The number of deals this deal stands for when the free suits are swapped, or 0 when another
+/// deal stands for it. Of all deals that only differ in the free suits, the one where the free
+/// suits are dealt in order stands for all of them. This only holds when the deals are played
+/// with the cards in hand in every order, the strategies break ties by that order.
+pub fn symmetry_weight(hands: &[Vec<Card>], free_suits: &[Suit]) -> u64 {
+    // for every free suit the player holding each rank, 0 when nobody holds it
+    let signatures: Vec<[usize; 13]> = free_suits.iter().map(|&suit| {
+        let mut signature = [0; 13];
+        for (player, hand) in hands.iter().enumerate() {
+            for card in hand.iter().filter(|card| card.suit() == suit) {
+                signature[card.rank() as usize] = player + 1;
+            }
+        }
+        signature
+    }).collect();
+
+    if signatures.windows(2).any(|pair| pair[0] > pair[1]) {
+        return 0;
+    }
+
+    let factorial = |n: usize| (1..=n as u64).product::<u64>();
+    let mut weight = factorial(signatures.len());
+    for group in signatures.chunk_by(|a, b| a == b) {
+        weight /= factorial(group.len());
+    }
+    weight
+}
+
+#[cfg(test)]
+mod test {
+    use crate::card::{card, create_deck, Card, Suit};
+    use crate::deal::Constraint;
+    use crate::exact::{count_deals, count_orders, for_each_order, free_suits, symmetry_weight, Deals};
+    use crate::game::RoundState;
+
+    #[test]
+    fn test_count_deals() {
+        assert_eq!(Some(51 * 50 * 49), count_deals(51, &[0, 1, 1, 1]));
+        assert_eq!(Some(10 * 3), count_deals(5, &[0, 2, 2]));
+        assert_eq!(Some(0), count_deals(3, &[2, 2]));
+        assert_eq!(None, count_deals(52, &[13, 13, 13, 13]));
+    }
+
+    #[test]
+    fn test_deals() {
+        let cards = create_deck(&[]);
+        let mut n = 0;
+        for hands in Deals::new(&cards[..5], &[0, 2, 2]) {
+            assert_eq!((0, 2, 2), (hands[0].len(), hands[1].len(), hands[2].len()));
+            assert!(!hands[1].iter().any(|card| hands[2].contains(card)));
+            n += 1;
+        }
+        assert_eq!(30, n);
+        assert_eq!(0, Deals::new(&cards[..3], &[2, 2]).count());
+
+        // the deals go on where the last one stopped
+        let mut deals = Deals::new(&cards[..5], &[0, 2, 2]);
+        let first: Vec<_> = deals.by_ref().take(10).collect();
+        assert_eq!(first, Deals::new(&cards[..5], &[0, 2, 2]).take(10).collect::<Vec<_>>());
+        assert_eq!(20, deals.count());
+    }
+
+    #[test]
+    fn test_for_each_order() {
+        assert_eq!(Some(2 * 6), count_orders(&[0, 2, 3]));
+        let mut hands: Vec<Vec<Card>> = vec!["Ks 2h".split(' ').map(card).collect(), "As 3d 2c".split(' ').map(card).collect()];
+        let mut orders = Vec::new();
+        for_each_order(&mut hands, 1, &mut |hands| {
+            assert_eq!(vec![card("Ks"), card("2h")], hands[0]);
+            orders.push(hands[1].clone());
+        });
+        orders.dedup();
+        assert_eq!(6, orders.len());
+        assert!(orders.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_symmetry() {
+        let mut state = RoundState::default();
+        assert_eq!(vec![Suit::Diamonds, Suit::Clubs], free_suits(Some(Suit::Hearts), &[card("2s")], &state));
+        state.constraints.push((1, Constraint::Void(Suit::Clubs)));
+        assert_eq!(vec![Suit::Diamonds], free_suits(Some(Suit::Hearts), &[card("2s")], &state));
+
+        let free = [Suit::Diamonds, Suit::Clubs];
+        let hands = |cards: [&str; 2]| vec![vec![], vec![card(cards[0])], vec![card(cards[1])]];
+        // swapping diamonds and clubs gives the same deal with clubs first
+        assert_eq!(2, symmetry_weight(&hands(["Ad", "Kc"]), &free));
+        assert_eq!(0, symmetry_weight(&hands(["Ac", "Kd"]), &free));
+        assert_eq!(0, symmetry_weight(&hands(["Ad", "Kd"]), &free));
+        assert_eq!(2, symmetry_weight(&hands(["Ac", "Kc"]), &free));
+        assert_eq!(1, symmetry_weight(&hands(["As", "Ah"]), &free));
+        assert_eq!(2, symmetry_weight(&hands(["As", "Kc"]), &free));
+
+        // every deal is counted exactly once over all representatives
+        let cards: Vec<Card> = ["2d", "3d", "2c", "3c", "As"].map(card).to_vec();
+        let (mut total, mut n) = (0, 0);
+        for hands in Deals::new(&cards, &[0, 1, 2]) {
+            total += symmetry_weight(&hands, &free);
+            n += 1;
+        }
+        assert_eq!(n, total);
+    }
+}
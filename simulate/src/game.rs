@@ -1,14 +1,20 @@
 use std::fmt::Display;
-use rand::prelude::SliceRandom;
-use rand::rngs::ThreadRng;
-use crate::card::{create_deck, highest_card, Card, Suit};
+use rand::{Rng, RngCore};
+use crate::card::{beats, Card, CardSet, Hand, Suit};
+use crate::deal::{Constraint, Dealer};
+use crate::query::MAX_PLAYERS;
+use crate::simulation::SimulateError;
+use crate::strategy::{estimate_tricks, BidView, Classic, PlayerView, Strategy};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 pub struct Player {
     pub starting_position: usize,
-    hand: Vec<Card>,
-    pub played: Vec<Card>,
+    hand: Hand,
+    pub played: CardSet,
     pub tricks: usize,
+    pub bid: Option<usize>,
+    // what the hand is worth when bidding, the same for every copy of a dealt game
+    estimate: f64,
 }
 
 impl Display for Player {
@@ -23,9 +29,11 @@ impl Player {
     pub fn new(starting_position: usize) -> Self {
         Player {
             starting_position,
-            hand: Vec::with_capacity(10),
+            hand: Hand::default(),
             tricks: 0,
-            played: Vec::with_capacity(10),
+            played: CardSet::EMPTY,
+            bid: None,
+            estimate: 0.0,
         }
     }
 
@@ -33,148 +41,333 @@ impl Player {
         self.hand.push(card);
     }
 
-    pub fn play_card(&mut self, previous_cards: &Vec<Card>, trump: Option<Suit>) -> Option<Card> {
-        if previous_cards.is_empty() {
-            // If no previous cards, play any card
-            return self.hand.pop().map(|card| {
-                self.played.push(card.clone());
-                card
-            });
+    /// Play the card `strategy` picks for the trick so far.
+    pub fn play_card<S: Strategy + ?Sized>(&mut self, previous_cards: &[Card], trump: Option<Suit>, strategy: &S, rng: &mut dyn RngCore) -> Option<Card> {
+        if self.hand.is_empty() {
+            return None;
         }
 
-        let first_suit = previous_cards.first()?.suit;
-        if let Some(card) = self.hand.iter().find(|&card| card.suit == first_suit).cloned() {
-            self.hand.retain(|c| c != &card);
-            self.played.push(card.clone());
-            Some(card)
-        } else {
-            let trumped = trump.map(|trump| previous_cards.first().unwrap().suit != trump && previous_cards.iter().any(|c|c.suit == trump)).unwrap_or(false);
-            if trumped {
-                let highest_trump = previous_cards.iter().filter(|c| c.suit == trump.unwrap()).max_by_key(|c| c.rank)?;
-                // if next card is no trump, play the card. if the next card is a trump and higher, play the card. if the next card is a trump and lower, pick the next card
-                let card = self.hand.iter().find(|&card| card.suit == trump.unwrap() && card.rank > highest_trump.rank)
-                    .or_else(|| self.hand.iter().find(|&card| card.suit != trump.unwrap()))
-                    .or_else(|| self.hand.first()).cloned()?;
-                self.hand.retain(|c| c != &card);
-                self.played.push(card.clone());
-                // if card.suit == trump.unwrap() && card.rank < highest_trump.rank {
-                //     println!("Trumped with lower card: {} < {}", card, highest_trump);
-                // }
-                Some(card)
-
-            } else {
-                self.hand.pop().map(|card| {
-                    self.played.push(card.clone());
-                    card
-                })
-            }
-        }
+        let view = PlayerView { hand: &self.hand, trick: previous_cards, trump, tricks: self.tricks, bid: self.bid };
+        // the last card in hand is played without asking the strategy
+        let index = match self.hand.len() {
+            1 => self.hand.first().unwrap(),
+            _ => strategy.choose_card(&view, rng),
+        };
+        let card = self.hand.remove(index);
+        self.played.insert(card);
+        Some(card)
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Game {
-    pub seed: usize,
-    pub players: Vec<Player>,
+/// A round in progress, seen from player 0. The other players follow player 0 in playing order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RoundState {
+    /// Cards of the finished tricks.
+    pub played: Vec<Card>,
+    /// Cards on the table in the current trick, in playing order. Player 0 plays next.
+    pub trick: Vec<Card>,
+    /// Tricks won so far by every player, starting with player 0. Empty when no trick was played.
+    pub won: Vec<usize>,
+    /// What is known about the hands of the other players, 1 is the player after player 0.
+    pub constraints: Vec<(usize, Constraint)>,
+}
+
+impl RoundState {
+    pub fn is_started(&self) -> bool {
+        !self.played.is_empty() || !self.trick.is_empty()
+    }
+
+    pub fn n_finished_tricks(&self) -> usize {
+        self.won.iter().sum()
+    }
+}
+
+/// The number of cards every other player holds when player 0 holds `n_cards` and the last
+/// `n_on_table` players already played to the current trick. Player 0 is not dealt to.
+pub fn hand_sizes(n_players: usize, n_cards: usize, n_on_table: usize) -> Vec<usize> {
+    (0..n_players).map(|index| match index {
+        0 => 0,
+        _ if index + n_on_table >= n_players => n_cards - 1,
+        _ => n_cards,
+    }).collect()
+}
+
+/// A game of one round. It lives on the stack, so copies of a dealt game can be played from every
+/// position without allocating. With the opponents' strategy as `S` instead of a trait object
+/// their choices are made without a virtual call.
+#[derive(Debug)]
+pub struct Game<'a, S: ?Sized = dyn Strategy> {
+    players: [Player; MAX_PLAYERS],
     n_players: usize,
     current_player: usize,
     pub trump: Option<Suit>,
+    // player 0 plays the classic strategy, the others play this one
+    opponents: &'a S,
+    known_bids: &'a [usize],
+    // a round in progress continues with player 0 or the players before player 0 who already
+    // put these cards on the table
+    in_progress: bool,
+    table: [Card; MAX_PLAYERS],
+    n_table: usize,
     played: bool
 }
 
-impl Game {
-    pub fn new(seed: usize, n_players: usize, trump: Option<Suit>, rng: &mut ThreadRng, player_cards: Vec<Card>) -> Self {
-        // if duplicate cards are found, panic
-        for i in 0..player_cards.len() {
-            for j in i+1..player_cards.len() {
-                if player_cards[i] == player_cards[j] {
-                    panic!("Duplicate cards found in player cards");
-                }
+// the strategy is only borrowed, so a game is copied whether or not the strategy can be
+impl<S: ?Sized> Clone for Game<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S: ?Sized> Copy for Game<'_, S> {}
+
+impl Game<'_> {
+    /// The dealer of the unseen cards for `from_dealer`, built once for every game of a round. A card
+    /// that is in `player_cards`, `state.played` and `state.trick` more than once is an error.
+    pub fn dealer(n_players: usize, trump: Option<Suit>, player_cards: &[Card], state: &RoundState) -> Result<Dealer, SimulateError> {
+        let mut seen = CardSet::EMPTY;
+        for &card in player_cards.iter().chain(&state.played).chain(&state.trick) {
+            if seen.contains(card) {
+                return Err(SimulateError::CardSeenTwice { card });
             }
+            seen.insert(card);
         }
 
-        let players = (0..n_players)
-            .map(|i| Player::new(i)).collect();
-        let mut game = Game {
-            seed,
+        let unseen: Vec<Card> = CardSet::DECK.difference(seen).iter().collect();
+        let hand_sizes = hand_sizes(n_players, player_cards.len(), state.trick.len());
+        Dealer::new(&unseen, &hand_sizes, &state.constraints, trump).ok_or(SimulateError::NoConsistentDeal)
+    }
+}
+
+impl<'a, S: Strategy + ?Sized> Game<'a, S> {
+    /// Deal a game in which player 0 holds `player_cards` and plays the classic strategy, the
+    /// other players get random cards and bid and play with `opponents`. In a round in progress
+    /// only the unseen cards are dealt: the players who already played to the current trick get
+    /// one card less than player 0, and the hands meet the constraints of the state.
+    pub fn from_state<R: Rng + ?Sized>(n_players: usize, trump: Option<Suit>, rng: &mut R, player_cards: Vec<Card>, state: &RoundState, opponents: &'a S) -> Result<Self, SimulateError> {
+        let dealer = Game::dealer(n_players, trump, &player_cards, state)?;
+        Ok(Self::from_dealer(&dealer, trump, rng, &Hand::from(&player_cards[..]), state, opponents))
+    }
+
+    /// Deal a game of a round in progress in which player 0 holds `player_hand`.
+    pub fn from_dealer<R: Rng + ?Sized>(dealer: &Dealer, trump: Option<Suit>, rng: &mut R, player_hand: &Hand, state: &RoundState, opponents: &'a S) -> Self {
+        let mut hands = [Hand::default(); MAX_PLAYERS];
+        let hands = &mut hands[..dealer.n_players()];
+        dealer.deal_into(hands, rng);
+        hands[0] = *player_hand;
+        Self::with_hands(trump, hands, state, opponents)
+    }
+
+    /// A game with known hands for every player, player 0 plays the classic strategy and the
+    /// others play `opponents`.
+    pub fn from_hands(trump: Option<Suit>, hands: &[Vec<Card>], state: &RoundState, opponents: &'a S) -> Self {
+        let hands: Vec<Hand> = hands.iter().map(|hand| Hand::from(&hand[..])).collect();
+        Self::with_hands(trump, &hands, state, opponents)
+    }
+
+    fn with_hands(trump: Option<Suit>, hands: &[Hand], state: &RoundState, opponents: &'a S) -> Self {
+        let n_players = hands.len();
+        let mut players = [Player::new(0); MAX_PLAYERS];
+        for (index, (player, hand)) in players.iter_mut().zip(hands).enumerate() {
+            let estimate = estimate_tricks(hand.set(), trump, n_players);
+            *player = Player { hand: *hand, estimate, ..Player::new(index) };
+        }
+        for (player, &won) in players.iter_mut().zip(&state.won) {
+            player.tricks = won;
+        }
+        let mut table = [Card::from_index(0); MAX_PLAYERS];
+        table[..state.trick.len()].copy_from_slice(&state.trick);
+
+        Game {
             players,
             n_players,
             current_player: 0,
             trump,
+            opponents,
+            known_bids: &[],
+            in_progress: state.is_started(),
+            table,
+            n_table: state.trick.len(),
             played: false,
-        };
+        }
+    }
 
-        let mut deck = create_deck(&player_cards);
-        deck.shuffle(rng);
-        game.deal_remaining_players(&mut deck, player_cards.len());
+    pub fn players(&self) -> &[Player] {
+        &self.players[..self.n_players]
+    }
 
-        game.players[0].hand = player_cards;
+    /// Bids of the first players to bid, made before the game is simulated. They replace the bids
+    /// of those players' strategies.
+    pub fn with_known_bids(mut self, bids: &'a [usize]) -> Self {
+        self.known_bids = bids;
+        self
+    }
 
-        game
+    // cheaper than taking the remainder, which divides
+    fn next_player(&self, index: usize) -> usize {
+        match index + 1 {
+            next if next == self.n_players => 0,
+            next => next,
+        }
     }
 
-    fn deal_remaining_players(&mut self, deck: &mut Vec<Card>, n_cards: usize) {
-        for player in &mut self.players.iter_mut().skip(1) {
-            for _ in 0..n_cards {
-                if let Some(card) = deck.pop() {
-                    player.add_card(card);
+    // the cards of the trick live on the stack, the card that wins so far and who played it are
+    // kept up to date as the cards are played
+    fn play_trick(&mut self, rng: &mut dyn RngCore) -> Option<usize> {
+        let mut trick = [Card::from_index(0); MAX_PLAYERS];
+        let mut n_played = 0;
+        let mut best: Option<(usize, Card)> = None;
+        let mut player_index = self.current_player;
+        for i in 0..self.n_players {
+            let played = match i < self.n_table {
+                true => Some(self.table[i]),
+                false => {
+                    let player = &mut self.players[player_index];
+                    match player_index {
+                        0 => player.play_card(&trick[..n_played], self.trump, &Classic, rng),
+                        _ => player.play_card(&trick[..n_played], self.trump, self.opponents, rng),
+                    }
+                }
+            };
+            if let Some(card) = played {
+                if best.is_none_or(|(_, best)| beats(card, best, self.trump)) {
+                    best = Some((player_index, card));
                 }
+                trick[n_played] = card;
+                n_played += 1;
             }
+            player_index = self.next_player(player_index);
         }
+        self.n_table = 0;
+
+        let (winner, _card) = best?;
+        self.current_player = winner;
+        Some(winner)
     }
 
-    fn play_trick(&mut self) -> Option<(usize, &Player)> {
-        let mut trick: Vec<Card> = Vec::new();
-        let mut player_order: Vec<usize> = Vec::new();
-        for i in 0..self.players.len() {
-            let player_index = (self.current_player + i) % self.players.len();
-            if let Some(card) = self.players[player_index].play_card(&trick, self.trump) {
-                player_order.push(player_index);
-                trick.push(card);
+    // every player bids in turn, starting with the player who leads the first trick. Player 0 does
+    // not bid, the simulation is asked how many tricks it takes without one, but the players after
+    // it hear the bid the classic strategy would make, so an opponent dealer still bids around the
+    // forbidden bid.
+    fn bid_round(&mut self, starting_player: usize) {
+        // player 0 has not played to the current trick yet
+        let n_cards = self.players[0].hand.len() + self.players().iter().map(|player| player.tricks).sum::<usize>();
+        let mut bids = [None; MAX_PLAYERS];
+        let mut index = starting_player;
+        for i in 0..self.n_players {
+            let player = &self.players[index];
+            let view = BidView { hand: &player.hand, estimate: player.estimate, trump: self.trump, n_players: self.n_players, n_cards, tricks: player.tricks, previous_bids: &bids[..i] };
+            // in a round in progress the tricks won so far count towards the bid
+            let bid = match self.known_bids.get(i) {
+                Some(&bid) => Some(bid),
+                None if index == 0 => Classic.bid(&view).map(|bid| bid + player.tricks),
+                None => self.opponents.bid(&view).map(|bid| bid + player.tricks),
+            };
+            if index != 0 {
+                self.players[index].bid = bid;
             }
+            bids[i] = bid;
+            index = self.next_player(index);
         }
+    }
 
-        let winner= highest_card(&trick, self.trump);
-
-        if let Some((winner_index, _card)) = winner {
-            self.current_player = player_order[winner_index];
-
-            /*if trick.first().unwrap().to_string() == "SA" && self.current_player != 0 && player_order[0] == 0 {
-                println!("Found the cards: {:?}", trick);
-                println!("Winner: {}", winner_index);
-                println!("Player order: {:?}", player_order);
-                println!("Current player: {}", self.current_player);
-            }*/
-
-            Some((self.current_player, &self.players[winner_index]))
-        } else {
-            None
+    /// Bid and play all tricks. Reshuffling changes the order in which players consider their
+    /// cards, which matters for strategies that pick the first or last matching card. In a round
+    /// in progress the current trick is finished first, whoever started the round.
+    pub fn play_game<R: RngCore>(&mut self, starting_player: usize, reshuffle: bool, rng: &mut R) {
+        self.start(starting_player);
+        if reshuffle {
+            self.reshuffle(rng);
         }
+        self.play_tricks(rng);
     }
 
-    pub fn play_game(&mut self, starting_player: usize, reshuffle: bool) {
+    /// Set the starting positions and let every player bid, the start of `play_game`. Bids do
+    /// not depend on the order of the cards in hand, so a started game can be copied to play it
+    /// both reshuffled and as dealt.
+    pub(crate) fn start(&mut self, starting_player: usize) {
         if self.played {
             panic!("Game has already been played");
         }
         self.played = true;
-        self.current_player = starting_player;
+        self.current_player = match self.in_progress {
+            true => (self.n_players - self.n_table) % self.n_players,
+            false => starting_player,
+        };
 
         // set starting positions
+        let mut index = starting_player;
         for i in 0..self.n_players {
-            self.players[(i + starting_player) % self.n_players].starting_position = i;
+            self.players[index].starting_position = i;
+            index = self.next_player(index);
         }
 
-        // reshuffle the player cards
-        if reshuffle {
-            for player in &mut self.players {
-                player.hand.shuffle(&mut rand::thread_rng());
-            }
+        self.bid_round(starting_player);
+    }
+
+    /// Put the cards of every player in a new random order.
+    pub(crate) fn reshuffle<R: RngCore>(&mut self, rng: &mut R) {
+        for player in &mut self.players[..self.n_players] {
+            player.hand.shuffle(rng);
         }
+    }
 
-        while self.players.iter().any(|player| !player.hand.is_empty()) {
-            if let Some((winner_idx, _winner)) = self.play_trick() {
-                { self.players[winner_idx].tricks += 1; }
+    /// Play all tricks of a started game.
+    pub(crate) fn play_tricks<R: RngCore>(&mut self, rng: &mut R) {
+        // every trick takes a card from every hand that has one
+        let n_tricks = self.players().iter().map(|player| player.hand.len()).max().unwrap_or(0);
+        for _ in 0..n_tricks {
+            if let Some(winner_idx) = self.play_trick(rng) {
+                self.players[winner_idx].tricks += 1;
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+    use crate::card::{card, create_hand_from_string, Card};
+    use crate::game::{Game, RoundState};
+    use crate::simulation::{SimulateError, SimulationRng};
+    use crate::strategy::Classic;
+
+    fn cards(s: &str) -> Vec<Card> {
+        create_hand_from_string(s).unwrap()
+    }
+
+    #[test]
+    fn test_dealer_rejects_duplicate_player_cards() {
+        let result = Game::dealer(4, None, &cards("As Ks As"), &RoundState::default());
+        assert_eq!(Some(SimulateError::CardSeenTwice { card: card("As") }), result.err());
+    }
+
+    #[test]
+    fn test_from_state_rejects_hands_that_cannot_be_dealt() {
+        let mut rng = SimulationRng::seed_from_u64(1);
+        let result = Game::from_state(4, None, &mut rng, cards("As Ks As"), &RoundState::default(), &Classic);
+        assert_eq!(Some(SimulateError::CardSeenTwice { card: card("As") }), result.err());
+
+        let spades = cards("2s 3s 4s 5s 6s 7s 8s 9s 10s Js Qs Ks As 2h");
+        let result = Game::from_state(4, None, &mut rng, spades, &RoundState::default(), &Classic);
+        assert_eq!(Some(SimulateError::NoConsistentDeal), result.err());
+    }
+
+    #[test]
+    fn test_dealer_rejects_played_player_cards() {
+        let played = RoundState { played: cards("2h 3h 4h As"), won: vec![1, 0, 0, 0], ..RoundState::default() };
+        assert_eq!(Some(SimulateError::CardSeenTwice { card: card("As") }), Game::dealer(4, None, &cards("As Ks"), &played).err());
+
+        let valid = RoundState { played: cards("2h 3h 4h 5h"), won: vec![1, 0, 0, 0], ..RoundState::default() };
+        assert!(Game::dealer(4, None, &cards("As Ks"), &valid).is_ok());
+    }
+
+    #[test]
+    fn test_dealer_rejects_player_cards_on_the_table() {
+        let trick = RoundState { trick: cards("Qh As"), ..RoundState::default() };
+        assert_eq!(Some(SimulateError::CardSeenTwice { card: card("As") }), Game::dealer(4, None, &cards("As Ks"), &trick).err());
+
+        let valid = RoundState { trick: cards("Qh Qs"), ..RoundState::default() };
+        assert!(Game::dealer(4, None, &cards("As Ks"), &valid).is_ok());
+    }
+}
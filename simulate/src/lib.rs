@@ -1,5 +1,14 @@
 pub mod card;
+pub mod deal;
+pub mod exact;
 pub mod game;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod parallel;
+pub mod query;
+pub mod recommend;
 pub mod simulation;
+pub mod simulator;
+pub mod solver;
+pub mod strategy;
 
 
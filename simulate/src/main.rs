@@ -1,13 +1,48 @@
-use futures::executor::block_on;
+use simulate::query::parse_query;
+use std::time::Duration;
 
-use simulate::simulation::{format_probabilities, simulate};
+use simulate::simulation::{format_probabilities, simulate, simulate_converged, Convergence, Progress};
+use simulate::solver::{parse_deal, solve};
 
+// usage: main [query] [seed] [number of games]    uses a thread per core
+//        main [query] [seed] [margin]%  e.g. 0.5% to run until every percentage is within ±0.5%, at most a minute
+//        main solve [trump] [leader] [hand]...  e.g. main solve h 0 "As 2h" "Ks 3h"
 fn main() {
-    block_on(async {
-        match simulate("5p c 2c 3c".to_string()).await {
-            Ok(result) => println!("{}", format_probabilities(&result)),
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|command| command == "solve") {
+        let trump = args.get(1).map_or("x", |trump| trump.as_str());
+        let leader = args.get(2).and_then(|leader| leader.parse().ok()).unwrap_or(0);
+        let result = parse_deal(args.get(3..).unwrap_or_default(), trump)
+            .and_then(|(hands, trump)| solve(&hands, trump, leader));
+        match result {
+            Ok(ranges) => {
+                for (player, range) in ranges.iter().enumerate() {
+                    println!("player {}: {} to {} tricks", player, range.min, range.max);
+                }
+            }
             Err(e) => eprintln!("Error: {}", e),
         }
-    });
+        return;
+    }
 
+    let input = args.first().cloned().unwrap_or("5p c 2c 3c".to_string());
+    let seed = args.get(1).and_then(|seed| seed.parse().ok()).unwrap_or_else(rand::random);
+    let margin = args.get(2).and_then(|margin| margin.strip_suffix('%')?.parse().ok());
+    let max_games = args.get(2).and_then(|n| n.parse().ok());
+    println!("seed {}", seed);
+    let forbidden_bid = parse_query(&input).ok().and_then(|query| query.forbidden_bid());
+
+    let result = match margin {
+        Some(margin) => {
+            let convergence = Convergence { margin, budget: Duration::from_secs(60) };
+            simulate_converged(input, seed, convergence, |progress: Progress| {
+                println!("precision ±{:.2}%", progress.precision)
+            })
+        }
+        None => simulate(input, seed, max_games, |_: Progress| {}),
+    };
+    match result {
+        Ok(result) => println!("{}", format_probabilities(&result, forbidden_bid)),
+        Err(e) => eprintln!("Error: {}", e),
+    }
 }
@@ -0,0 +1,122 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use rand::SeedableRng;
+use crate::simulation::{Counts, Probability, Progress, SimulateError, SimulationRng, Setup};
+
+/// Games played by a worker before it hands its counts over.
+pub const CHUNK_SIZE: usize = 1000;
+
+/// The number of worker threads to use by default, one per available core.
+pub fn default_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Simulate `input` on `n_threads` worker threads until `stop` returns true for the merged
+/// probabilities, or exactly `max_games` games when given. `callback` gets the progress of the
+/// merged probabilities every 250 ms.
+///
+/// Games are played in chunks and every chunk draws from its own stream of the seeded generator,
+/// so the same seed and `max_games` give the same result for any number of threads.
+pub fn simulate_parallel<Fc, Fs>(
+    input: &str,
+    seed: u64,
+    max_games: Option<usize>,
+    n_threads: usize,
+    callback: Option<Fc>,
+    mut stop: Fs,
+) -> Result<Vec<Probability>, SimulateError>
+where
+    Fc: FnMut(Progress),
+    Fs: FnMut(&[Probability]) -> bool,
+{
+    let setup = Setup::new(input)?;
+    let mut callback = callback;
+    if let Some(probabilities) = setup.exact(&mut SimulationRng::seed_from_u64(seed))? {
+        if let Some(ref mut cb) = callback {
+            cb(Progress::new(probabilities.clone()));
+        }
+        return Ok(probabilities);
+    }
+
+    let next_chunk = AtomicUsize::new(0);
+    let done = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel::<Counts>();
+
+    std::thread::scope(|scope| {
+        for _ in 0..n_threads.max(1) {
+            let sender = sender.clone();
+            let (setup, next_chunk, done) = (&setup, &next_chunk, &done);
+            scope.spawn(move || {
+                while !done.load(Ordering::SeqCst) {
+                    let chunk = next_chunk.fetch_add(1, Ordering::SeqCst);
+                    let first_game = chunk * CHUNK_SIZE;
+                    let n_games = max_games.map_or(CHUNK_SIZE, |max| CHUNK_SIZE.min(max.saturating_sub(first_game)));
+                    if n_games == 0 {
+                        break;
+                    }
+
+                    let mut rng = SimulationRng::seed_from_u64(seed);
+                    rng.set_stream(chunk as u64);
+                    let mut counts = setup.counts();
+                    setup.play(n_games, &mut rng, &mut counts);
+                    if sender.send(counts).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // the workers hold the only senders, so the channel closes when the last one finishes
+        drop(sender);
+
+        let callback_interval = Duration::from_millis(250);
+        let mut last_callback_time = Instant::now();
+        let mut counts = setup.counts();
+        loop {
+            match receiver.recv_timeout(Duration::from_millis(50)) {
+                Ok(chunk_counts) => counts.merge(&chunk_counts),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+
+            // the chunks that are still running are dropped
+            if stop(&setup.probabilities(&counts)) {
+                done.store(true, Ordering::SeqCst);
+                break;
+            }
+
+            let now = Instant::now();
+            if now.duration_since(last_callback_time) >= callback_interval {
+                if let Some(ref mut cb) = callback {
+                    cb(Progress::new(setup.probabilities(&counts)));
+                }
+                last_callback_time = now;
+            }
+        }
+        Ok(setup.probabilities(&counts))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parallel::simulate_parallel;
+    use crate::simulation::{counts, games_counted, Progress};
+
+    fn run(input: &str, seed: u64, max_games: usize, n_threads: usize) -> Vec<(usize, usize, usize)> {
+        counts(&simulate_parallel(input, seed, Some(max_games), n_threads, None::<fn(Progress)>, |_| false).unwrap())
+    }
+
+    #[test]
+    fn test_same_result_for_any_number_of_threads() {
+        let one = run("4p h 2s 4s AH", 42, 2500, 1);
+        assert_eq!(one, run("4p h 2s 4s AH", 42, 2500, 4));
+        assert_ne!(one, run("4p h 2s 4s AH", 43, 2500, 4));
+        assert_eq!(2500, games_counted(&one, 4));
+    }
+
+    #[test]
+    fn test_stop() {
+        let probabilities = simulate_parallel("5p c 2c 3c", 1, None, 3, None::<fn(Progress)>, |_| true).unwrap();
+        assert_eq!(5 * 3, probabilities.len());
+    }
+}
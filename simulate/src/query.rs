@@ -0,0 +1,399 @@
+use crate::card::{Card, Suit};
+use crate::deal::Constraint;
+use crate::game::RoundState;
+use crate::simulation::SimulateError;
+use crate::strategy::StrategyKind;
+
+pub const MIN_PLAYERS: usize = 2;
+pub const MAX_PLAYERS: usize = 7;
+const DECK_SIZE: usize = 52;
+
+/// A parsed simulation query like `"4p h 2s 4s"`: the number of players, the trump suit (`x` for
+/// no trump) and the cards in the hand of the simulated player. Options like `vs=random` can go
+/// anywhere after the number of players.
+///
+/// `seat=3` is the seat relative to the dealer: 1 is left of the dealer and bids and leads
+/// first, the dealer sits at the number of players. `bids=1,0` are the bids made before this
+/// seat, in bidding order.
+///
+/// A round in progress is given by the cards left in the hand and `played=2s,3s,..` for the
+/// cards of the finished tricks, `trick=9h,Kh` for the cards on the table and `won=1,0,0,0` for
+/// the tricks won by every player, starting with the simulated player in playing order.
+///
+/// What is known about the other players is given per player, `p1` is the player after the
+/// simulated player: `p1.void=h,s` for the suits they do not have, `p2.has=As` for cards they
+/// hold and `p3.trumps=2` for the minimum number of trumps they hold. Players who did not
+/// follow suit in the current trick are void in the led suit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub n_players: usize,
+    pub trump: Option<Suit>,
+    pub cards: Vec<Card>,
+    pub opponents: StrategyKind,
+    pub seat: Option<usize>,
+    pub bids: Option<Vec<usize>>,
+    pub state: RoundState,
+}
+
+impl Query {
+    /// The number of cards dealt to every player this round.
+    pub fn n_cards(&self) -> usize {
+        self.cards.len() + self.state.n_finished_tricks()
+    }
+
+    /// The bid the dealer may not make, because all bids would add up to the number of cards.
+    pub fn forbidden_bid(&self) -> Option<usize> {
+        let bids = self.bids.as_ref()?;
+        if self.seat != Some(self.n_players) {
+            return None;
+        }
+        self.n_cards().checked_sub(bids.iter().sum())
+    }
+}
+
+/// A word of the query and the character position it starts at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Token<'a> {
+    pub position: usize,
+    pub text: &'a str,
+}
+
+pub(crate) fn tokenize(input: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start: Option<(usize, usize)> = None;
+    for (position, (index, c)) in input.char_indices().enumerate() {
+        match (c.is_whitespace(), start) {
+            (true, Some((start_position, start_index))) => {
+                tokens.push(Token { position: start_position, text: &input[start_index..index] });
+                start = None;
+            }
+            (false, None) => start = Some((position, index)),
+            _ => {}
+        }
+    }
+    if let Some((position, index)) = start {
+        tokens.push(Token { position, text: &input[index..] });
+    }
+    tokens
+}
+
+fn parse_players(token: Token) -> Result<usize, SimulateError> {
+    let n_players: usize = token.text.strip_suffix('p')
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| SimulateError::InvalidPlayers { position: token.position, token: token.text.to_string() })?;
+    if !(MIN_PLAYERS..=MAX_PLAYERS).contains(&n_players) {
+        return Err(SimulateError::PlayersOutOfRange { position: token.position, n_players });
+    }
+    Ok(n_players)
+}
+
+pub(crate) fn parse_trump(token: Token) -> Result<Option<Suit>, SimulateError> {
+    match token.text {
+        "x" | "X" => Ok(None),
+        text => text.parse::<Suit>()
+            .map(Some)
+            .map_err(|_| SimulateError::InvalidTrump { position: token.position, token: text.to_string() }),
+    }
+}
+
+pub(crate) fn parse_card(token: Token, seen: &[&[Card]]) -> Result<Card, SimulateError> {
+    let card = Card::from_string(token.text)
+        .ok_or_else(|| SimulateError::InvalidCard { position: token.position, token: token.text.to_string() })?;
+    if seen.iter().any(|cards| cards.contains(&card)) {
+        return Err(SimulateError::DuplicateCard { position: token.position, card });
+    }
+    Ok(card)
+}
+
+// the comma separated values of an option like `played=2s,3s`
+fn option_values(token: Token) -> Vec<Token> {
+    let (key, value) = token.text.split_once('=').unwrap_or((token.text, ""));
+    let mut position = token.position + key.chars().count() + 1;
+    value.split(',').filter(|text| !text.is_empty()).map(|text| {
+        let value = Token { position, text };
+        position += text.chars().count() + 1;
+        value
+    }).collect()
+}
+
+fn parse_option(token: Token, query: &mut Query) -> Result<(), SimulateError> {
+    let (key, value) = token.text.split_once('=').unwrap_or((token.text, ""));
+    match key {
+        "vs" => {
+            query.opponents = value.parse()
+                .map_err(|_| SimulateError::InvalidStrategy { position: token.position, token: value.to_string() })?;
+        }
+        "seat" => {
+            let seat = value.parse().ok().filter(|seat| (1..=query.n_players).contains(seat))
+                .ok_or_else(|| SimulateError::InvalidSeat { position: token.position, token: value.to_string() })?;
+            query.seat = Some(seat);
+        }
+        "bids" => {
+            let bids: Option<Vec<usize>> = match value {
+                "" => Some(Vec::new()),
+                _ => value.split(',').map(|bid| bid.parse().ok()).collect(),
+            };
+            query.bids = Some(bids.filter(|bids| bids.len() < query.n_players)
+                .ok_or_else(|| SimulateError::InvalidBids { position: token.position, token: value.to_string() })?);
+        }
+        "played" => {
+            for value in option_values(token) {
+                let card = parse_card(value, &[&query.cards, &query.state.played, &query.state.trick])?;
+                query.state.played.push(card);
+            }
+        }
+        "trick" => {
+            for value in option_values(token) {
+                let card = parse_card(value, &[&query.cards, &query.state.played, &query.state.trick])?;
+                query.state.trick.push(card);
+            }
+            if query.state.trick.len() >= query.n_players {
+                return Err(SimulateError::InvalidTrick { position: token.position, n_cards: query.state.trick.len() });
+            }
+        }
+        _ if key.starts_with('p') && key.contains('.') => parse_constraint(token, query)?,
+        "won" => {
+            // nobody wins more tricks than a round has, so the sum of the values cannot overflow
+            let max_won = DECK_SIZE / query.n_players;
+            let won: Option<Vec<usize>> = value.split(',')
+                .map(|won| won.parse().ok().filter(|&won| won <= max_won))
+                .collect();
+            query.state.won = won.filter(|won| won.len() == query.n_players)
+                .ok_or_else(|| SimulateError::InvalidWon { position: token.position, token: value.to_string() })?;
+        }
+        _ => return Err(SimulateError::UnknownOption { position: token.position, token: token.text.to_string() }),
+    }
+    Ok(())
+}
+
+fn parse_constraint(token: Token, query: &mut Query) -> Result<(), SimulateError> {
+    let (key, value) = token.text.split_once('=').unwrap_or((token.text, ""));
+    let invalid = || SimulateError::InvalidConstraint { position: token.position, token: token.text.to_string() };
+    let (player, kind) = key.split_once('.').ok_or_else(invalid)?;
+    let player: usize = player[1..].parse().ok()
+        .filter(|player| (1..query.n_players).contains(player))
+        .ok_or_else(invalid)?;
+
+    match kind {
+        "void" => {
+            for value in option_values(token) {
+                let suit = value.text.parse().map_err(|_| invalid())?;
+                query.state.constraints.push((player, Constraint::Void(suit)));
+            }
+        }
+        "has" => {
+            for value in option_values(token) {
+                let held: Vec<Card> = query.state.constraints.iter()
+                    .filter_map(|(_, constraint)| match constraint {
+                        Constraint::Holds(card) => Some(*card),
+                        _ => None,
+                    })
+                    .collect();
+                let card = parse_card(value, &[&query.cards, &query.state.played, &query.state.trick, &held])?;
+                query.state.constraints.push((player, Constraint::Holds(card)));
+            }
+        }
+        "trumps" if query.trump.is_some() => {
+            let n = value.parse().ok().filter(|&n| n <= query.n_cards()).ok_or_else(invalid)?;
+            query.state.constraints.push((player, Constraint::MinTrumps(n)));
+        }
+        _ => return Err(invalid()),
+    }
+    Ok(())
+}
+
+// players who did not follow the led suit in the current trick are void in it
+fn infer_voids(query: &mut Query) {
+    let Some(led) = query.state.trick.first().map(|card| card.suit()) else {
+        return;
+    };
+    let first_player = query.n_players - query.state.trick.len();
+    for (i, card) in query.state.trick.iter().enumerate().skip(1) {
+        let void = (first_player + i, Constraint::Void(led));
+        if card.suit() != led && !query.state.constraints.contains(&void) {
+            query.state.constraints.push(void);
+        }
+    }
+}
+
+pub fn parse_query(input: &str) -> Result<Query, SimulateError> {
+    let tokens = tokenize(input);
+    let end = input.chars().count();
+
+    let players = *tokens.first().ok_or(SimulateError::MissingPlayers { position: end })?;
+    let mut query = Query {
+        n_players: parse_players(players)?,
+        trump: None,
+        cards: Vec::new(),
+        opponents: StrategyKind::default(),
+        seat: None,
+        bids: None,
+        state: RoundState::default(),
+    };
+
+    let (options, positional): (Vec<Token>, Vec<Token>) = tokens.into_iter().skip(1).partition(|token| token.text.contains('='));
+    query.trump = parse_trump(*positional.first().ok_or(SimulateError::MissingTrump { position: end })?)?;
+    for &token in positional.iter().skip(1) {
+        let card = parse_card(token, &[&query.cards])?;
+        query.cards.push(card);
+    }
+
+    // constraints come last, so the cards they hold can be checked against all other cards
+    let (constraints, options): (Vec<Token>, Vec<Token>) = options.into_iter().partition(|token| token.text.starts_with('p') && token.text.contains('.'));
+    for &token in options.iter().chain(&constraints) {
+        parse_option(token, &mut query)?;
+    }
+    infer_voids(&mut query);
+
+    if query.cards.is_empty() || query.n_cards() * query.n_players > DECK_SIZE {
+        return Err(SimulateError::InvalidHandSize { n_cards: query.n_cards(), n_players: query.n_players });
+    }
+
+    let n_played = query.state.played.len();
+    if n_played != query.state.n_finished_tricks() * query.n_players {
+        return Err(SimulateError::PlayedDoesNotMatchWon { n_played, n_tricks: query.state.n_finished_tricks() });
+    }
+
+    if let Some(bids) = &query.bids {
+        if let Some(&token) = options.iter().find(|token| token.text.starts_with("bids=")) {
+            if bids.iter().any(|&bid| bid > query.n_cards()) {
+                return Err(SimulateError::InvalidBids { position: token.position, token: token.text["bids=".len()..].to_string() });
+            }
+        }
+        match query.seat {
+            Some(seat) if seat != bids.len() + 1 => return Err(SimulateError::BidsDoNotMatchSeat { n_bids: bids.len(), seat }),
+            _ => query.seat = Some(bids.len() + 1),
+        }
+    }
+
+    Ok(query)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::card::{card, Suit};
+    use crate::deal::Constraint;
+    use crate::game::RoundState;
+    use crate::query::{parse_query, tokenize, Query, Token};
+    use crate::simulation::SimulateError;
+    use crate::strategy::StrategyKind;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(vec![
+            Token { position: 1, text: "4p" },
+            Token { position: 5, text: "♥" },
+            Token { position: 7, text: "10s" },
+        ], tokenize(" 4p  ♥ 10s"));
+        assert!(tokenize("   ").is_empty());
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(Ok(Query {
+            n_players: 4,
+            trump: Some(Suit::Hearts),
+            cards: vec![card("2s"), card("10s"), card("AH")],
+            opponents: StrategyKind::Classic,
+            seat: None,
+            bids: None,
+            state: RoundState::default(),
+        }), parse_query("4p h 2s 10s AH"));
+        assert_eq!(StrategyKind::BidAware, parse_query("4p vs=bid h 2s").unwrap().opponents);
+        assert_eq!(StrategyKind::RandomLegal, parse_query("4p h 2s vs=random").unwrap().opponents);
+        assert_eq!(None, parse_query("7p x kd").unwrap().trump);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Err(SimulateError::MissingPlayers { position: 0 }), parse_query(""));
+        assert_eq!(Err(SimulateError::InvalidPlayers { position: 0, token: "4x".to_string() }), parse_query("4x h 2s"));
+        assert_eq!(Err(SimulateError::PlayersOutOfRange { position: 1, n_players: 8 }), parse_query(" 8p h 2s"));
+        assert_eq!(Err(SimulateError::PlayersOutOfRange { position: 0, n_players: 1 }), parse_query("1p h 2s"));
+        assert_eq!(Err(SimulateError::MissingTrump { position: 2 }), parse_query("4p"));
+        assert_eq!(Err(SimulateError::InvalidTrump { position: 3, token: "q".to_string() }), parse_query("4p q 2s"));
+        assert_eq!(Err(SimulateError::InvalidCard { position: 8, token: "1s".to_string() }), parse_query("4p h 2s 1s"));
+        assert_eq!(Err(SimulateError::DuplicateCard { position: 8, card: card("2s") }), parse_query("4p h 2s 2S"));
+        assert_eq!(Err(SimulateError::InvalidHandSize { n_cards: 0, n_players: 4 }), parse_query("4p h"));
+        assert_eq!(Err(SimulateError::InvalidStrategy { position: 8, token: "smart".to_string() }), parse_query("4p h 2s vs=smart"));
+        assert_eq!(Err(SimulateError::UnknownOption { position: 3, token: "x=1".to_string() }), parse_query("4p x=1 h 2s"));
+        assert_eq!(Err(SimulateError::InvalidSeat { position: 8, token: "5".to_string() }), parse_query("4p h 2s seat=5"));
+        assert_eq!(Err(SimulateError::InvalidBids { position: 8, token: "1,x".to_string() }), parse_query("4p h 2s bids=1,x"));
+        assert_eq!(Err(SimulateError::InvalidBids { position: 8, token: "2".to_string() }), parse_query("4p h 2s bids=2"));
+        assert_eq!(Err(SimulateError::BidsDoNotMatchSeat { n_bids: 1, seat: 4 }), parse_query("4p h 2s bids=0 seat=4"));
+    }
+
+    #[test]
+    fn test_seat_and_bids() {
+        let query = parse_query("4p h 2s 3s bids=1,0").unwrap();
+        assert_eq!((Some(3), Some(vec![1, 0])), (query.seat, query.bids));
+        assert_eq!(Some(1), parse_query("3p seat=1 h 2s bids=").unwrap().seat);
+        assert_eq!(Some(2), parse_query("4p h 2s seat=2").unwrap().seat);
+    }
+
+    #[test]
+    fn test_round_in_progress() {
+        let query = parse_query("3p h 2s 3s played=4s,5s,6s trick=9h,Kh won=0,1,0").unwrap();
+        assert_eq!(RoundState {
+            played: vec![card("4s"), card("5s"), card("6s")],
+            trick: vec![card("9h"), card("Kh")],
+            won: vec![0, 1, 0],
+            constraints: Vec::new(),
+        }, query.state);
+        assert_eq!(3, query.n_cards());
+        assert_eq!(Some(0), parse_query("3p h 2s 3s played=4s,5s,6s won=0,1,0 bids=1,2").unwrap().forbidden_bid());
+
+        assert_eq!(Err(SimulateError::DuplicateCard { position: 17, card: card("2s") }), parse_query("3p h 2s 3s trick=2s"));
+        assert_eq!(Err(SimulateError::InvalidCard { position: 24, token: "1h".to_string() }), parse_query("3p h 2s 3s played=9h,Kh,1h won=1,0,0"));
+        assert_eq!(Err(SimulateError::InvalidTrick { position: 11, n_cards: 3 }), parse_query("3p h 2s 3s trick=4s,5s,6s"));
+        assert_eq!(Err(SimulateError::InvalidWon { position: 11, token: "1,0".to_string() }), parse_query("3p h 2s 3s won=1,0"));
+        assert_eq!(Err(SimulateError::InvalidWon { position: 11, token: "18446744073709551615,1,0".to_string() }), parse_query("3p h 2s 3s won=18446744073709551615,1,0"));
+        assert_eq!(Err(SimulateError::PlayedDoesNotMatchWon { n_played: 2, n_tricks: 1 }), parse_query("3p h 2s 3s played=4s,5s won=1,0,0"));
+    }
+
+    #[test]
+    fn test_constraints() {
+        let query = parse_query("4p h 2s 3s p1.void=s,c p2.has=As,Kd p3.trumps=2").unwrap();
+        assert_eq!(vec![
+            (1, Constraint::Void(Suit::Spades)),
+            (1, Constraint::Void(Suit::Clubs)),
+            (2, Constraint::Holds(card("As"))),
+            (2, Constraint::Holds(card("Kd"))),
+            (3, Constraint::MinTrumps(2)),
+        ], query.state.constraints);
+
+        // the second and third player before us did not follow hearts
+        let query = parse_query("4p h 2s 3s trick=9h,2c,Kd played=4s,5s,6s,7s won=0,1,0,0").unwrap();
+        assert_eq!(vec![(2, Constraint::Void(Suit::Hearts)), (3, Constraint::Void(Suit::Hearts))], query.state.constraints);
+
+        assert_eq!(Err(SimulateError::InvalidConstraint { position: 11, token: "p4.void=h".to_string() }), parse_query("4p h 2s 3s p4.void=h"));
+        assert_eq!(Err(SimulateError::InvalidConstraint { position: 11, token: "p1.void=q".to_string() }), parse_query("4p h 2s 3s p1.void=q"));
+        assert_eq!(Err(SimulateError::InvalidConstraint { position: 11, token: "p1.trumps=2".to_string() }), parse_query("4p x 2s 3s p1.trumps=2"));
+        assert_eq!(Err(SimulateError::InvalidConstraint { position: 11, token: "p1.trumps=3".to_string() }), parse_query("4p h 2s 3s p1.trumps=3"));
+        assert_eq!(Err(SimulateError::InvalidConstraint { position: 11, token: "p1.short=h".to_string() }), parse_query("4p h 2s 3s p1.short=h"));
+        assert_eq!(Err(SimulateError::DuplicateCard { position: 28, card: card("As") }), parse_query("4p h 2s 3s p1.has=As p2.has=As"));
+        assert_eq!(Err(SimulateError::DuplicateCard { position: 18, card: card("2s") }), parse_query("4p h 2s 3s p1.has=2s"));
+    }
+
+    #[test]
+    fn test_forbidden_bid() {
+        assert_eq!(Some(1), parse_query("4p h 2s 3s 4s bids=1,0,1").unwrap().forbidden_bid());
+        assert_eq!(Some(0), parse_query("3p h 2s 3s bids=1,1").unwrap().forbidden_bid());
+        // bids already add up to more than the number of cards, every bid is allowed
+        assert_eq!(None, parse_query("3p h 2s 3s bids=2,1").unwrap().forbidden_bid());
+        // not the dealer, or the bids before the dealer are unknown
+        assert_eq!(None, parse_query("4p h 2s 3s bids=1,0").unwrap().forbidden_bid());
+        assert_eq!(None, parse_query("4p h 2s 3s seat=4").unwrap().forbidden_bid());
+    }
+
+    #[test]
+    fn test_hand_size_for_player_count() {
+        let deck: Vec<String> = ["s", "h", "d", "c"].iter()
+            .flat_map(|suit| ["2", "3", "4", "5", "6", "7", "8", "9", "10", "J", "Q", "K", "A"].map(|rank| format!("{}{}", rank, suit)))
+            .collect();
+        let hand = |n: usize| deck[..n].join(" ");
+        assert!(parse_query(&format!("7p s {}", hand(7))).is_ok());
+        assert_eq!(Err(SimulateError::InvalidHandSize { n_cards: 8, n_players: 7 }), parse_query(&format!("7p s {}", hand(8))));
+        assert!(parse_query(&format!("2p s {}", hand(26))).is_ok());
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt::Display;
+use serde::Serialize;
+use crate::simulation::Probability;
+
+/// The scoring variants of the score sheet, rounds are scored exactly as the app scores them.
+pub use serializer::score::Scoring;
+
+/// Expected score of one bid and its variance.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BidScore {
+    pub bid: usize,
+    pub expected_score: f64,
+    pub variance: f64,
+}
+
+/// Scores of every possible bid from one starting position.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BidRecommendation {
+    pub starting_position: usize,
+    pub bids: Vec<BidScore>,
+    /// The bid the dealer may not make, it is never the best bid.
+    pub forbidden_bid: Option<usize>,
+    /// The allowed bid with the highest expected score, the safest one on a tie.
+    pub best_bid: usize,
+}
+
+impl Display for BidRecommendation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Bids pos {}:", self.starting_position)?;
+        for score in &self.bids {
+            let marker = match score.bid {
+                bid if bid == self.best_bid => " <==",
+                bid if Some(bid) == self.forbidden_bid => " not allowed",
+                _ => "",
+            };
+            writeln!(f, " {}: {:.2} ± {:.2}{}", score.bid, score.expected_score, score.variance.sqrt(), marker)?;
+        }
+        Ok(())
+    }
+}
+
+/// Score every bid against the trick distribution of each starting position. Positions without
+/// simulated games are left out.
+pub fn recommend_bids(probabilities: &[Probability], scoring: Scoring, forbidden_bid: Option<usize>) -> Vec<BidRecommendation> {
+    let mut positions: Vec<usize> = probabilities.iter().map(|p| p.starting_position).collect();
+    positions.dedup();
+
+    positions.into_iter().filter_map(|starting_position| {
+        let distribution: Vec<&Probability> = probabilities.iter()
+            .filter(|p| p.starting_position == starting_position)
+            .collect();
+        let total: usize = distribution.iter().map(|p| p.count).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let n_cards = distribution.iter().map(|p| p.tricks).max().unwrap_or(0);
+        let bids: Vec<BidScore> = (0..=n_cards).map(|bid| {
+            let weighted = |f: &dyn Fn(f64) -> f64| -> f64 {
+                distribution.iter()
+                    .map(|p| f(scoring.round_score(bid as u8, p.tricks as u8, n_cards as u8) as f64) * p.count as f64)
+                    .sum::<f64>() / total as f64
+            };
+            let expected_score = weighted(&|score| score);
+            let variance = weighted(&|score| (score - expected_score).powi(2));
+            BidScore { bid, expected_score, variance }
+        }).collect();
+
+        let best_bid = bids.iter()
+            .filter(|score| Some(score.bid) != forbidden_bid)
+            .max_by(|a, b| a.expected_score.total_cmp(&b.expected_score).then(b.variance.total_cmp(&a.variance)))
+            .map_or(0, |score| score.bid);
+
+        Some(BidRecommendation { starting_position, bids, forbidden_bid, best_bid })
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::recommend::{recommend_bids, Scoring};
+    use crate::simulation::Probability;
+
+    // trick counts per number of tricks from one starting position
+    fn distribution(starting_position: usize, counts: &[usize]) -> Vec<Probability> {
+        let total: usize = counts.iter().sum();
+        counts.iter().enumerate().map(|(tricks, &count)| Probability {
+            n_players: 4,
+            starting_position,
+            tricks,
+            percentage: count as f64 / total.max(1) as f64 * 100.0,
+            count,
+            exact: false,
+            lower: 0.0,
+            upper: 100.0,
+        }).collect()
+    }
+
+    #[test]
+    fn test_expected_score_and_variance() {
+        let recommendations = recommend_bids(&distribution(0, &[1, 3]), Scoring::Standard, None);
+        let scores: Vec<(usize, f64, f64)> = recommendations[0].bids.iter()
+            .map(|score| (score.bid, score.expected_score, score.variance))
+            .collect();
+        // bid 0: 5 a quarter of the time, 1 otherwise; bid 1: 0 a quarter of the time, 6 otherwise
+        assert_eq!(vec![(0, 2.0, 3.0), (1, 4.5, 6.75)], scores);
+        assert_eq!(1, recommendations[0].best_bid);
+    }
+
+    #[test]
+    fn test_scoring_changes_best_bid() {
+        let probabilities = distribution(2, &[5, 3, 2]);
+        assert_eq!(0, recommend_bids(&probabilities, Scoring::Standard, None)[0].best_bid);
+        assert_eq!(0, recommend_bids(&probabilities, Scoring::ZeroBidBonus, None)[0].best_bid);
+        let probabilities = distribution(2, &[3, 4, 3]);
+        assert_eq!(1, recommend_bids(&probabilities, Scoring::TenPlusBid, None)[0].best_bid);
+    }
+
+    #[test]
+    fn test_ties_prefer_lower_variance() {
+        // bid 0 and bid 2 both expect 3.5, bid 0 always scores something
+        let recommendations = recommend_bids(&distribution(0, &[1, 0, 1]), Scoring::Standard, None);
+        assert_eq!(3.5, recommendations[0].bids[0].expected_score);
+        assert_eq!(3.5, recommendations[0].bids[2].expected_score);
+        assert_eq!(0, recommendations[0].best_bid);
+    }
+
+    #[test]
+    fn test_forbidden_bid_is_never_best() {
+        let recommendations = recommend_bids(&distribution(3, &[1, 3]), Scoring::Standard, Some(1));
+        assert_eq!(Some(1), recommendations[0].forbidden_bid);
+        assert_eq!(0, recommendations[0].best_bid);
+        // the forbidden bid is still scored, to show what the hook costs
+        assert_eq!(4.5, recommendations[0].bids[1].expected_score);
+        assert!(recommendations[0].to_string().contains(" 1: 4.50 ± 2.60 not allowed"));
+    }
+
+    #[test]
+    fn test_positions_without_games_are_skipped() {
+        let mut probabilities = distribution(0, &[0, 0]);
+        probabilities.extend(distribution(1, &[2, 2]));
+        let recommendations = recommend_bids(&probabilities, Scoring::default(), None);
+        assert_eq!(1, recommendations.len());
+        assert_eq!(1, recommendations[0].starting_position);
+    }
+}
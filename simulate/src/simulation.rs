@@ -1,242 +1,377 @@
-use std::collections::HashMap;
 use std::fmt::Display;
-use std::ops::Deref;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, LazyLock, Mutex};
-use serde::{Serialize};
-use web_time::Instant;
-use crate::card::{create_hand_from_string, Card, Suit};
-use crate::game::Game;
+use std::sync::Arc;
+use std::time::Duration;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use crate::card::{create_deck, Card, Hand, Suit};
+use crate::deal::{meets_constraints, Dealer};
+use crate::exact::{count_deals, count_orders, for_each_order, free_suits, symmetry_weight, Deals, MAX_EXACT_GAMES};
+use crate::query::{parse_query, Query, MAX_PLAYERS, MIN_PLAYERS};
+use crate::game::{hand_sizes, Game};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::parallel::{default_threads, simulate_parallel};
+use crate::recommend::{recommend_bids, Scoring};
+use crate::strategy::{BidAware, Classic, GreedyHighest, LowestThatWins, RandomLegal, Strategy, StrategyKind};
 use wasm_bindgen::prelude::*;
-use js_sys;
-use wasm_bindgen_futures::JsFuture;
-use web_sys::window;
-use wasm_bindgen::closure::Closure;
 
-#[derive(Debug)]
+/// Every deal is played from every position twice, as dealt and reshuffled.
+const GAMES_PER_DEAL: usize = 2;
+
+/// Errors of a simulation. Query errors carry the character position of the offending word.
+#[derive(Debug, PartialEq)]
 pub enum SimulateError {
-    Error(String),
+    MissingPlayers { position: usize },
+    InvalidPlayers { position: usize, token: String },
+    PlayersOutOfRange { position: usize, n_players: usize },
+    MissingTrump { position: usize },
+    InvalidTrump { position: usize, token: String },
+    InvalidCard { position: usize, token: String },
+    DuplicateCard { position: usize, card: Card },
+    InvalidHandSize { n_cards: usize, n_players: usize },
+    UnknownOption { position: usize, token: String },
+    InvalidStrategy { position: usize, token: String },
+    InvalidSeat { position: usize, token: String },
+    InvalidBids { position: usize, token: String },
+    BidsDoNotMatchSeat { n_bids: usize, seat: usize },
+    InvalidTrick { position: usize, n_cards: usize },
+    InvalidWon { position: usize, token: String },
+    PlayedDoesNotMatchWon { n_played: usize, n_tricks: usize },
+    InvalidConstraint { position: usize, token: String },
+    NoConsistentDeal,
+    InvalidLeader { leader: usize, n_players: usize },
+    UnequalHands { sizes: Vec<usize> },
+    CardInTwoHands { card: Card },
+    CardSeenTwice { card: Card },
+    UnknownScoring { scoring: String },
+    UnknownCard { token: String },
+    Serialization(String),
 }
 
 impl Display for SimulateError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Error(msg) => write!(f, "{}", msg)
+            Self::MissingPlayers { position } => write!(f, "{}: expected the number of players, like 4p", position + 1),
+            Self::InvalidPlayers { position, token } => write!(f, "{}: '{}' is not a number of players, like 4p", position + 1, token),
+            Self::PlayersOutOfRange { position, n_players } => write!(f, "{}: {} players, expected {} to {}", position + 1, n_players, MIN_PLAYERS, MAX_PLAYERS),
+            Self::MissingTrump { position } => write!(f, "{}: expected a trump suit s, h, d, c or x for no trump", position + 1),
+            Self::InvalidTrump { position, token } => write!(f, "{}: '{}' is not a trump suit, expected s, h, d, c or x", position + 1, token),
+            Self::InvalidCard { position, token } => write!(f, "{}: '{}' is not a card, like 10h or As", position + 1, token),
+            Self::DuplicateCard { position, card } => write!(f, "{}: {} is already in the hand", position + 1, card),
+            Self::InvalidHandSize { n_cards, n_players } => write!(f, "{} cards cannot be dealt to {} players", n_cards, n_players),
+            Self::UnknownOption { position, token } => write!(f, "{}: unknown option '{}'", position + 1, token),
+            Self::InvalidStrategy { position, token } => write!(f, "{}: '{}' is not a strategy, expected classic, random, greedy, lowest or bid", position + 1, token),
+            Self::InvalidSeat { position, token } => write!(f, "{}: '{}' is not a seat, expected 1 (left of the dealer) up to the number of players (the dealer)", position + 1, token),
+            Self::InvalidBids { position, token } => write!(f, "{}: '{}' are not bids, expected the bids before yours like 1,0,2", position + 1, token),
+            Self::BidsDoNotMatchSeat { n_bids, seat } => write!(f, "{} bids were made before seat {}, expected {}", n_bids, seat, seat - 1),
+            Self::InvalidTrick { position, n_cards } => write!(f, "{}: {} cards on the table, but the trick is complete before it is your turn", position + 1, n_cards),
+            Self::InvalidWon { position, token } => write!(f, "{}: '{}' are not the tricks won, expected one number per player like 1,0,2,0", position + 1, token),
+            Self::PlayedDoesNotMatchWon { n_played, n_tricks } => write!(f, "{} cards were played, but {} tricks were won", n_played, n_tricks),
+            Self::InvalidConstraint { position, token } => write!(f, "{}: '{}' is not a constraint, like p1.void=h, p2.has=As or p3.trumps=2", position + 1, token),
+            Self::NoConsistentDeal => write!(f, "no deal meets all constraints"),
+            Self::InvalidLeader { leader, n_players } => write!(f, "player {} cannot lead, there are {} players", leader, n_players),
+            Self::UnequalHands { sizes } => write!(f, "every hand needs the same number of cards, got {:?}", sizes),
+            Self::CardInTwoHands { card } => write!(f, "{} is in two hands", card),
+            Self::CardSeenTwice { card } => write!(f, "{} is held or played more than once", card),
+            Self::UnknownScoring { scoring } => write!(f, "'{}' is not a scoring, expected standard, missPenalty, tenPlusBid or zeroBidBonus", scoring),
+            Self::UnknownCard { token } => write!(f, "'{}' is not a card, like 10h or As", token),
+            Self::Serialization(msg) => write!(f, "could not convert between Rust and JavaScript values: {}", msg)
         }
     }
 }
 
-impl <E>From<E> for SimulateError
-where E: std::error::Error {
-    fn from(e: E) -> Self {
-        SimulateError::Error(format!("{:?}", e))
+impl From<serde_wasm_bindgen::Error> for SimulateError {
+    fn from(e: serde_wasm_bindgen::Error) -> Self {
+        SimulateError::Serialization(e.to_string())
     }
 }
 
-impl Into<JsValue> for SimulateError {
-    fn into(self) -> JsValue {
-        JsValue::from_str(&self.to_string())
+impl From<SimulateError> for JsValue {
+    fn from(e: SimulateError) -> Self {
+        JsValue::from_str(&e.to_string())
     }
 }
 
-// Global stop flag for the simulation
-static STOP_SIMULATION: LazyLock<Mutex<AtomicBool>> = LazyLock::new(|| Mutex::new(AtomicBool::new(false)));
-
-#[cfg(target_arch = "wasm32")]
-async fn sleep(ms: i32) -> Result<(), JsValue> {
-    JsFuture::from(js_sys::Promise::new(&mut |resolve, _| {
-        window()
-            .unwrap()
-            .set_timeout_with_callback_and_timeout_and_arguments_0(
-                Closure::once_into_js(move || resolve.call0(&JsValue::NULL).unwrap())
-                    .as_ref()
-                    .unchecked_ref(),
-                ms,
-            )
-            .unwrap();
-    }))
-        .await
-        .map(|_| ())
-}
+/// Random number generator of the simulations. ChaCha gives the same numbers for the same seed on
+/// every platform, so a seeded simulation gives the same probabilities on native and on wasm.
+pub type SimulationRng = ChaCha8Rng;
 
+/// Expected score and variance of every bid for the probabilities of a simulation of `input`,
+/// scored with `scoring` ("standard", "missPenalty", "tenPlusBid" or "zeroBidBonus", standard by
+/// default). The dealer's forbidden bid follows from the seat and bids in the query.
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
-pub async fn simulate(input: String, callback: js_sys::Function) -> Result<Vec<JsValue>, SimulateError> {
-    // Store the stop flag in the global variable
-    STOP_SIMULATION.lock().unwrap().store(false, Ordering::SeqCst);
-
-    // Create a callback wrapper that converts Rust Probability to JS
-    let callback_wrapper = move |probabilities: Vec<Probability>| {
-        let js_values: Result<Vec<JsValue>, _> = probabilities
-            .iter()
-            .map(|p| p.to_js_value())
-            .collect();
-
-        if let Ok(values) = js_values {
-            let this = JsValue::null();
-            let js_array = js_sys::Array::from_iter(values.iter());
-            let _ = callback.call1(&this, &js_array);
-        }
+pub fn recommend(input: String, probabilities: JsValue, scoring: Option<String>) -> Result<JsValue, SimulateError> {
+    let forbidden_bid = parse_query(&input)?.forbidden_bid();
+    let probabilities: Vec<Probability> = serde_wasm_bindgen::from_value(probabilities)?;
+    let scoring = match scoring {
+        Some(scoring) => scoring.parse().map_err(|_| SimulateError::UnknownScoring { scoring })?,
+        None => Scoring::default(),
     };
+    Ok(serde_wasm_bindgen::to_value(&recommend_bids(&probabilities, scoring, forbidden_bid))?)
+}
 
-    // Run the simulation with the callback and stop flag
-    let final_result = simulate_impl(input, Some(callback_wrapper), || STOP_SIMULATION.lock().unwrap().load(Ordering::SeqCst)).await?;
+/// Simulate for 2 seconds, or exactly `max_games` games when given, on a worker thread per core,
+/// calling `callback` with the intermediate progress.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn simulate(input: String, seed: u64, max_games: Option<usize>, callback: impl FnMut(Progress)) -> Result<Vec<Probability>, SimulateError> {
+    let start_time = std::time::Instant::now();
+    simulate_parallel(
+        &input,
+        seed,
+        max_games,
+        default_threads(),
+        Some(callback),
+        |_: &[Probability]| max_games.is_none() && start_time.elapsed() >= Duration::from_secs(2),
+    )
+}
 
-    // Convert the final result to JS values
-    final_result
-        .iter()
-        .map(|p| p.to_js_value())
-        .collect()
+/// Simulate on a worker thread per core until the probabilities have converged, calling
+/// `callback` with the intermediate progress.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn simulate_converged(input: String, seed: u64, convergence: Convergence, callback: impl FnMut(Progress)) -> Result<Vec<Probability>, SimulateError> {
+    let start_time = std::time::Instant::now();
+    simulate_parallel(
+        &input,
+        seed,
+        None,
+        default_threads(),
+        Some(callback),
+        |probabilities: &[Probability]| convergence.is_reached(probabilities, start_time.elapsed()),
+    )
 }
 
-#[cfg(target_arch = "wasm32")]
-#[wasm_bindgen]
-pub fn stop_simulation() {
-    STOP_SIMULATION.lock().unwrap().store(true, Ordering::SeqCst);
+/// When a simulation has run long enough: once every percentage is within `margin` percentage
+/// points of the true one, see `precision`, or once `budget` has passed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Convergence {
+    pub margin: f64,
+    pub budget: Duration,
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-pub async fn simulate(input: String) -> Result<Vec<Probability>, SimulateError> {
-    // set stop flag after 2 seconds
-    let flag = Arc::new(AtomicBool::new(false));
-    let mover = Arc::clone(&flag);
-    std::thread::spawn(move || {
-        std::thread::sleep(std::time::Duration::from_secs(2));
-        mover.store(true, Ordering::SeqCst);
-    });
-
-    simulate_impl(input, Some(move |probabilities: Vec<Probability>| {println!("intermediate")}), move || flag.load(Ordering::SeqCst)).await
+impl Convergence {
+    pub fn is_reached(&self, probabilities: &[Probability], elapsed: Duration) -> bool {
+        elapsed >= self.budget || precision(probabilities) <= self.margin
+    }
 }
 
-// parse the input string and simulate the game
-// return the result as a string
-// input example: "4p h 2s 4s"
-// where 4p is the number of players, h is the trump suit, 2s 4s are the cards in the hand
-// the trump suit can be x for no trump
-// #[cfg(target_arch = "wasm32")]
-async fn simulate_impl<Fc, Fs>(
-    input: String,
-    callback: Option<Fc>,
-    stop: Fs,
-) -> Result<Vec<Probability>, SimulateError>
-where
-    Fc: FnMut(Vec<Probability>),
-    Fs: Fn() -> bool,
-{
-    let parts: Vec<&str> = input.split_whitespace().collect();
-    if parts.len() < 3 {
-        return Err(SimulateError::Error("Invalid input".to_string()));
-    }
-
-    let n_players: usize = parts[0].trim_end_matches('p').parse().unwrap_or(0);
-    let trump = match parts[1] {
-        "x" => None,
-        _ => Some(parts[1].parse::<Suit>().unwrap()),
-    };
-    let player_cards = create_hand_from_string(&parts[2..].join(" "));
+/// Where a pass over every possible deal is, and the tricks taken in the deals played so far.
+pub(crate) struct ExactPass {
+    deals: Deals,
+    free: Vec<Suit>,
+    own_orders: usize,
+    counts: Counts,
+    done: bool,
+}
 
-    let mut rng = rand::thread_rng();
-    let mut counts = HashMap::new();
+/// A parsed query and the positions it is played from, shared by every chunk of games.
+pub(crate) struct Setup {
+    query: Query,
+    positions: Vec<usize>,
+    opponents: Arc<dyn Strategy>,
+    known_bids: Vec<usize>,
+    dealer: Dealer,
+    hand: Hand,
+}
 
-    let start_time = Instant::now();
-    let mut last_callback_time = start_time;
-    let callback_interval = std::time::Duration::from_millis(250);
+impl Setup {
+    pub(crate) fn new(input: &str) -> Result<Self, SimulateError> {
+        let query = parse_query(input)?;
+        // with a known seat only the games in which the simulated player sits there are played, a
+        // round in progress without a seat is counted from the player who led the current trick
+        let positions: Vec<usize> = match query.seat {
+            Some(seat) => vec![seat - 1],
+            None if query.state.is_started() => vec![query.state.trick.len()],
+            None => (0..query.n_players).collect(),
+        };
+        let opponents = query.opponents.strategy();
+        let known_bids = query.bids.clone().unwrap_or_default();
+        let dealer = Game::dealer(query.n_players, query.trump, &query.cards, &query.state)?;
+        let hand = Hand::from(&query.cards[..]);
+        Ok(Setup { query, positions, opponents, known_bids, dealer, hand })
+    }
 
-    let mut i = 0;
-    let mut callback = callback;
-    let chunk_size = 10000; // Process 5 simulations per chunk
+    /// The probabilities over every possible deal, None when there are too many deals to play.
+    pub(crate) fn exact(&self, rng: &mut SimulationRng) -> Result<Option<Vec<Probability>>, SimulateError> {
+        let Some(mut pass) = self.exact_pass() else {
+            return Ok(None);
+        };
+        self.play_exact(&mut pass, usize::MAX, rng);
+        self.exact_probabilities(&pass)
+    }
 
-    loop {
-        // Check if we should stop
-        if stop() {
-            break;
+    /// A pass over every possible deal, None when there are too many games to play or the
+    /// opponents play randomly.
+    pub(crate) fn exact_pass(&self) -> Option<ExactPass> {
+        let query = &self.query;
+        let seen: Vec<Card> = query.cards.iter().chain(&query.state.played).chain(&query.state.trick).cloned().collect();
+        let unseen = create_deck(&seen);
+        let hand_sizes = hand_sizes(query.n_players, query.cards.len(), query.state.trick.len());
+        let own_orders = count_orders(&[query.cards.len()])?;
+        let games = count_deals(unseen.len(), &hand_sizes)?
+            .checked_mul(count_orders(&hand_sizes)?)?
+            .checked_mul(own_orders + 1)?
+            .checked_mul(self.positions.len() as u64)?;
+        if self.opponents.is_random() || games > MAX_EXACT_GAMES {
+            return None;
         }
 
-        // Process a chunk of simulations
-        for _ in 0..chunk_size {
-            let game = Game::new(i, n_players, trump, &mut rng, player_cards.clone());
-            for pid in 0..n_players {
-                for reshuffle in [true, false].iter() {
-                    let mut g = game.clone();
-                    g.play_game(pid, *reshuffle);
-
-                    let mut stats = Stat::from_game(&g);
-                    for stat in stats.drain(..) {
-                        *counts.entry(stat).or_insert(0) += 1;
-                    }
-                }
+        Some(ExactPass {
+            deals: Deals::new(&unseen, &hand_sizes),
+            free: free_suits(query.trump, &query.cards, &query.state),
+            own_orders: own_orders as usize,
+            counts: self.counts(),
+            done: false,
+        })
+    }
+
+    /// Play the next deals of `pass` until at least `n_games` games were played and return how
+    /// many. Fewer are played when the last deal was played.
+    ///
+    /// Every deal is played from every position, in every order of the cards in hand. Like the
+    /// sampled games, every deal is played as dealt, the opponents' cards in every order and player
+    /// 0's as given, and reshuffled, with every hand in every order. Deals that only differ in suits
+    /// that play no role are played once.
+    pub(crate) fn play_exact(&self, pass: &mut ExactPass, n_games: usize, rng: &mut SimulationRng) -> usize {
+        let query = &self.query;
+        let mut n_played = 0;
+        while n_played < n_games {
+            let Some(mut hands) = pass.deals.next() else {
+                pass.done = true;
+                break;
+            };
+            let weight = symmetry_weight(&hands, &pass.free) as usize;
+            if weight == 0 || !meets_constraints(&hands, &query.state.constraints, query.trump) {
+                continue;
             }
-            i += 1;
-        }
 
-        // Check if it's time to send an update
-        let now = Instant::now();
-        if now.duration_since(last_callback_time) >= callback_interval {
-            if let Some(ref mut cb) = callback {
-                let probabilities = calculate_probability(&counts, &player_cards, n_players, trump);
-                cb(probabilities);
-                last_callback_time = now;
+            for &position in &self.positions {
+                let mut play = |hands: &[Vec<Card>], weight: usize| {
+                    let mut game = Game::from_hands(query.trump, hands, &query.state, self.opponents.as_ref())
+                        .with_known_bids(&self.known_bids);
+                    game.play_game((query.n_players - position) % query.n_players, false, rng);
+                    pass.counts.add(&game, weight);
+                    n_played += 1;
+                };
+                // as dealt player 0's cards are in the order of the query, these games count as
+                // much as the reshuffled ones, which play every order of player 0's cards
+                hands[0] = query.cards.clone();
+                for_each_order(&mut hands, 1, &mut |hands| play(hands, weight * pass.own_orders));
+                for_each_order(&mut hands, 0, &mut |hands| play(hands, weight));
             }
+        }
+        n_played
+    }
+
+    /// The probabilities over every possible deal once `pass` played them all, None before.
+    pub(crate) fn exact_probabilities(&self, pass: &ExactPass) -> Result<Option<Vec<Probability>>, SimulateError> {
+        if !pass.done {
+            return Ok(None);
+        }
+        if pass.counts.is_empty() {
+            return Err(SimulateError::NoConsistentDeal);
+        }
+        let mut probabilities = self.probabilities(&pass.counts);
+        for probability in &mut probabilities {
+            probability.exact = true;
+            probability.lower = probability.percentage;
+            probability.upper = probability.percentage;
+        }
+        Ok(Some(probabilities))
+    }
+
+    /// Deal `n_games` random games and play each from every position, with and without
+    /// reshuffling, adding the tricks taken to `counts`.
+    pub(crate) fn play(&self, n_games: usize, rng: &mut SimulationRng, counts: &mut Counts) {
+        match self.query.opponents {
+            StrategyKind::Classic => self.play_against(&Classic, n_games, rng, counts),
+            StrategyKind::RandomLegal => self.play_against(&RandomLegal, n_games, rng, counts),
+            StrategyKind::GreedyHighest => self.play_against(&GreedyHighest, n_games, rng, counts),
+            StrategyKind::LowestThatWins => self.play_against(&LowestThatWins, n_games, rng, counts),
+            StrategyKind::BidAware => self.play_against(&BidAware, n_games, rng, counts),
+        }
+    }
+
+    // `play` for every strategy of the opponents on its own, so the games call the strategy
+    // directly instead of through `self.opponents`
+    fn play_against<S: Strategy>(&self, opponents: &S, n_games: usize, rng: &mut SimulationRng, counts: &mut Counts) {
+        let Query { n_players, trump, ref state, .. } = self.query;
+        for _ in 0..n_games {
+            let game = Game::from_dealer(&self.dealer, trump, rng, &self.hand, state, opponents)
+                .with_known_bids(&self.known_bids);
+            for &position in &self.positions {
+                // player 0 is the simulated player. Both games of the position bid the same, so
+                // the bids are made once
+                let mut started = game;
+                started.start((n_players - position) % n_players);
+                for reshuffle in [true, false] {
+                    let mut g = started;
+                    if reshuffle {
+                        g.reshuffle(rng);
+                    }
+                    g.play_tricks(rng);
 
-            #[cfg(target_arch = "wasm32")]
-            {
-                // Yield to the browser to keep the UI responsive
-                // Use sleep to yield control back to the JavaScript event loop
-                if let Err(e) = sleep(0).await {
-                    return Err(SimulateError::Error(format!("Error yielding to browser: {:?}", e)));
+                    counts.add(&g, 1);
                 }
             }
         }
     }
 
-    Ok(calculate_probability(&counts, &player_cards, n_players, trump))
+    /// Counts without any game, to `play` into.
+    pub(crate) fn counts(&self) -> Counts {
+        Counts::new(self.query.n_players, self.query.n_cards())
+    }
+
+    pub(crate) fn probabilities(&self, counts: &Counts) -> Vec<Probability> {
+        calculate_probability(counts, self.query.n_players, &self.positions)
+    }
 }
 
-pub fn format_probabilities(probabilities: &Vec<Probability>) -> String {
+/// The trick distributions followed by the expected score of every bid with standard scoring.
+/// Pass the dealer's forbidden bid to recommend the best allowed bid instead.
+pub fn format_probabilities(probabilities: &[Probability], forbidden_bid: Option<usize>) -> String {
     let mut result = String::new();
     for p in probabilities {
         result.push_str(&format!("Probabilities pos {}:\n", p.starting_position));
-        result.push_str(&format!(" {}: {:.2}%\n", p.tricks, p.percentage));
-        result.push_str(&format!("Simulations: {}\n", p.count));
+        if p.exact {
+            result.push_str(&format!(" {}: {:.2}%\n", p.tricks, p.percentage));
+        } else {
+            result.push_str(&format!(" {}: {:.2}% ({:.2}% to {:.2}%)\n", p.tricks, p.percentage, p.lower, p.upper));
+        }
+        result.push_str(&format!("Simulations: {}{}\n", p.count, if p.exact { " (exact)" } else { "" }));
+    }
+    if !probabilities.iter().all(|p| p.exact) {
+        result.push_str(&format!("Precision: ±{:.2}%\n", precision(probabilities)));
+    }
+    for recommendation in recommend_bids(probabilities, Scoring::Standard, forbidden_bid) {
+        result.push_str(&recommendation.to_string());
     }
     result
 }
 
-fn calculate_probability(counts: &HashMap<Stat, usize>, player_cards: &Vec<Card>, n_players: usize, trump: Option<Suit>) -> Vec<Probability> {
+fn calculate_probability(counts: &Counts, n_players: usize, positions: &[usize]) -> Vec<Probability> {
     let mut probabilities = Vec::new();
-    let mut cards = player_cards.clone();
-    cards.sort();
-    for starting_position in 0..n_players {
-        let mut total_count = 0;
-        let mut stat = Stat {
-            cards: cards.clone(),
-            starting_position,
-            tricks: 0,
-            trump,
-        };
-
-        for trick_count in 0..=cards.len() {
-            stat.tricks = trick_count;
-            if let Some(count) = counts.get(&stat) {
-                total_count += count;
-            }
-        }
-
-        for trick_count in 0..=cards.len() {
-            stat.tricks = trick_count;
-            let count = counts.get(&stat).unwrap_or(&0);
+    for &starting_position in positions {
+        let total_count = counts.total(starting_position);
+        for trick_count in 0..counts.n_tricks {
+            let count = counts.get(starting_position, trick_count);
             let percentage = if total_count > 0 {
-                (*count as f64 / total_count as f64) * 100.0
+                (count as f64 / total_count as f64) * 100.0
             } else {
                 0.0
             };
 
+            // both games of a deal, as dealt and reshuffled, are counted, but they are not
+            // independent, so the interval is as wide as if only the deals were counted
+            let (lower, upper) = confidence_interval(count, total_count, total_count / GAMES_PER_DEAL);
             let prob = Probability {
                 n_players,
                 starting_position,
                 tricks: trick_count,
                 percentage,
-                count: *count,
+                count,
+                exact: false,
+                lower,
+                upper,
             };
 
             probabilities.push(prob);
@@ -247,61 +382,218 @@ fn calculate_probability(counts: &HashMap<Stat, usize>, player_cards: &Vec<Card>
     probabilities
 }
 
+/// The 95% Wilson score interval of `count` out of `total` in percent, as if `samples` independent
+/// samples were taken. It stays within 0 to 100 and is not empty for counts of 0 or `total`.
+pub fn confidence_interval(count: usize, total: usize, samples: usize) -> (f64, f64) {
+    if total == 0 || samples == 0 {
+        return (0.0, 100.0);
+    }
+    let z: f64 = 1.96;
+    let n = samples as f64;
+    let p = count as f64 / total as f64;
+    let denominator = 1.0 + z * z / n;
+    let center = (p + z * z / (2.0 * n)) / denominator;
+    let half_width = z * (p * (1.0 - p) / n + z * z / (4.0 * n * n)).sqrt() / denominator;
+    (((center - half_width) * 100.0).max(0.0), ((center + half_width) * 100.0).min(100.0))
+}
+
+/// How far the true percentages may lie from the simulated ones: the largest distance of any
+/// percentage to either end of its interval, in percentage points.
+pub fn precision(probabilities: &[Probability]) -> f64 {
+    probabilities.iter()
+        .map(|p| (p.percentage - p.lower).max(p.upper - p.percentage))
+        .fold(0.0, f64::max)
+}
+
+/// The probabilities of a simulation so far and how precise they are, see `precision`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Progress {
+    pub probabilities: Vec<Probability>,
+    pub precision: f64,
+}
+
+impl Progress {
+    pub fn new(probabilities: Vec<Probability>) -> Self {
+        let precision = precision(&probabilities);
+        Progress { probabilities, precision }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn to_js_value(&self) -> Result<JsValue, SimulateError> {
+        Ok(serde_wasm_bindgen::to_value(self)?)
+    }
+}
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Probability {
     pub n_players: usize,
     pub starting_position: usize,
     pub tricks: usize,
     pub percentage: f64,
     pub count: usize,
+    /// Counted over every possible deal instead of sampled ones.
+    #[serde(default)]
+    pub exact: bool,
+    /// The 95% confidence interval of the percentage, equal to it when exact.
+    #[serde(default)]
+    pub lower: f64,
+    #[serde(default)]
+    pub upper: f64,
 }
 
-impl Probability {
-    #[cfg(target_arch = "wasm32")]
-    fn to_js_value(&self) -> Result<JsValue, SimulateError> {
-        let result = serde_wasm_bindgen::to_value(self);
-        match result {
-            Ok(value) => Ok(value),
-            Err(e) => Err(SimulateError::Error(format!("{:?}", e)))
+/// The number of games in which the simulated player took every number of tricks, per starting
+/// position. The cards of the simulated player and trump are the same in every game of a query.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Counts {
+    // tricks range up to the number of cards in the round, which is larger than the hand in a
+    // round in progress
+    n_tricks: usize,
+    counts: Vec<usize>,
+}
+
+impl Counts {
+    pub(crate) fn new(n_players: usize, n_cards: usize) -> Self {
+        Counts { n_tricks: n_cards + 1, counts: vec![0; n_players * (n_cards + 1)] }
+    }
+
+    fn add<S: Strategy + ?Sized>(&mut self, game: &Game<S>, count: usize) {
+        let player = &game.players()[0];
+        self.counts[player.starting_position * self.n_tricks + player.tricks] += count;
+    }
+
+    fn get(&self, starting_position: usize, tricks: usize) -> usize {
+        self.counts[starting_position * self.n_tricks + tricks]
+    }
+
+    fn total(&self, starting_position: usize) -> usize {
+        self.counts[starting_position * self.n_tricks..][..self.n_tricks].iter().sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.counts.iter().all(|&count| count == 0)
+    }
+
+    pub(crate) fn merge(&mut self, other: &Counts) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
-pub struct Stat {
-    cards: Vec<Card>,
-    starting_position: usize,
-    tricks: usize,
-    trump: Option<Suit>,
+/// (starting position, tricks, count) of every probability, to compare results in tests.
+#[cfg(test)]
+pub(crate) fn counts(probabilities: &[Probability]) -> Vec<(usize, usize, usize)> {
+    probabilities.iter().map(|p| (p.starting_position, p.tricks, p.count)).collect()
 }
 
-impl Display for Stat {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s_trump = match self.trump {
-            Some(trump) => format!("{}", trump),
-            None => "no".to_string(),
-        };
+/// The number of games behind `counts`: every game is played from every starting position, with
+/// and without reshuffling.
+#[cfg(test)]
+pub(crate) fn games_counted(counts: &[(usize, usize, usize)], n_positions: usize) -> usize {
+    counts.iter().map(|c| c.2).sum::<usize>() / (GAMES_PER_DEAL * n_positions)
+}
 
-        let s_cards = self.cards.iter().map(|card| format!("{}", card)).collect::<Vec<String>>().join(" ");
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use rand::SeedableRng;
+    use crate::simulation::{confidence_interval, counts, games_counted, Convergence, Setup, SimulationRng};
+    use crate::simulator::Simulator;
+
+    fn run(input: &str, seed: u64) -> Vec<(usize, usize, usize)> {
+        let mut simulator = Simulator::new(input.to_string(), Some(seed)).unwrap();
+        // a pass over every deal takes as many steps as it needs
+        while !simulator.is_done() && simulator.games() < 200 {
+            simulator.step(200 - simulator.games()).unwrap();
+        }
+        counts(&simulator.probabilities())
+    }
 
-        write!(f, "p{} - {} with {} trump  ==> {} tricks", self.starting_position+1, s_cards, s_trump, self.tricks)
+    #[test]
+    fn test_same_seed_same_result() {
+        assert_eq!(run("4p h 2s 4s AH", 42), run("4p h 2s 4s AH", 42));
+        assert_ne!(run("4p h 2s 4s AH", 42), run("4p h 2s 4s AH", 43));
+    }
+
+    #[test]
+    fn test_seat_plays_one_position() {
+        let counts = run("4p h 2s 4s AH bids=1,0,1", 5);
+        assert!(counts.iter().all(|c| c.0 == 3));
+        assert_eq!(200, games_counted(&counts, 1));
+    }
+
+    #[test]
+    fn test_round_in_progress() {
+        // the ace of hearts wins the current trick, one more trick is left
+        let counts = run("3p h AH 2s played=4s,5s,6s trick=9h,Kh won=0,1,0", 3);
+        assert!(counts.iter().all(|c| c.0 == 2));
+        let tricks: Vec<usize> = counts.iter().filter(|c| c.2 > 0).map(|c| c.1).collect();
+        assert!(tricks.iter().all(|&t| t == 1 || t == 2));
+        // few enough cards are unseen to play every deal of one card to both other players, twice
+        // as dealt and once for both orders of player 0's cards reshuffled
+        assert_eq!(45 * 44 * 4, counts.iter().map(|c| c.2).sum::<usize>());
+    }
+
+    #[test]
+    fn test_exact() {
+        let mut simulator = Simulator::new("2p x AS".to_string(), Some(1)).unwrap();
+        while !simulator.is_done() {
+            simulator.step(1).unwrap();
+        }
+        let probabilities = simulator.probabilities();
+        assert!(probabilities.iter().all(|p| p.exact));
+        let counts = counts(&probabilities);
+        // leading the ace always wins, following only wins when a spade was led. Every deal is
+        // played as dealt and reshuffled.
+        assert_eq!(vec![(0, 0, 0), (0, 1, 102), (1, 0, 78), (1, 1, 24)], counts);
+    }
+
+    #[test]
+    fn test_exact_matches_sampling() {
+        for input in ["2p x 9s 9h", "2p h 9s 2h", "3p h AH 2s played=4s,5s,6s trick=9h,Kh won=0,1,0"] {
+            let setup = Setup::new(input).unwrap();
+            let mut rng = SimulationRng::seed_from_u64(17);
+            let exact = setup.exact(&mut rng).unwrap().unwrap();
+            let mut counts = setup.counts();
+            setup.play(100_000, &mut rng, &mut counts);
+            let sampled = setup.probabilities(&counts);
+
+            assert_eq!(exact.len(), sampled.len());
+            for (exact, sampled) in exact.iter().zip(&sampled) {
+                assert_eq!((exact.starting_position, exact.tricks), (sampled.starting_position, sampled.tricks));
+                assert!((exact.percentage - sampled.percentage).abs() < 0.5, "{}: {:?} {:?}", input, exact, sampled);
+            }
+        }
+    }
+
+    #[test]
+    fn test_constraints_change_the_result() {
+        // the next player holds the ace of spades, the king can never win
+        let counts = run("3p x KS p1.has=AS", 3);
+        assert!(counts.iter().all(|c| c.1 == 0 || c.2 == 0));
+    }
+
+    #[test]
+    fn test_confidence_interval() {
+        let round = |(lower, upper): (f64, f64)| ((lower * 100.0).round() / 100.0, (upper * 100.0).round() / 100.0);
+        assert_eq!((40.38, 59.62), round(confidence_interval(50, 100, 100)));
+        assert_eq!((40.38, 59.62), round(confidence_interval(100, 200, 100)));
+        // never outside 0 to 100, but not empty when nothing was counted
+        assert_eq!((0.0, 3.7), round(confidence_interval(0, 100, 100)));
+        assert_eq!((96.3, 100.0), round(confidence_interval(100, 100, 100)));
+        assert_eq!((0.0, 100.0), confidence_interval(0, 0, 0));
     }
-}
 
-impl Stat {
-    fn from_game(game: &Game) -> Vec<Stat> {
-        let mut stats = Vec::new();
-        for player in &game.players {
-            let mut cards = player.played.clone();
-            cards.sort();
-            stats.push(Stat {
-                cards,
-                starting_position: player.starting_position,
-                tricks: player.tricks,
-                trump: game.trump,
-            });
+    #[test]
+    fn test_convergence() {
+        let mut simulator = Simulator::new("3p x KD QS 2C".to_string(), Some(2)).unwrap();
+        simulator.step(100).unwrap();
+        let convergence = Convergence { margin: 2.0, budget: Duration::from_secs(60) };
+        assert!(!convergence.is_reached(&simulator.probabilities(), Duration::ZERO));
+        assert!(convergence.is_reached(&simulator.probabilities(), Duration::from_secs(60)));
+        while simulator.precision() > 2.0 {
+            simulator.step(1000).unwrap();
         }
-        stats
+        assert!(convergence.is_reached(&simulator.probabilities(), Duration::ZERO));
     }
 }
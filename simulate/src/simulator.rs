@@ -0,0 +1,210 @@
+use rand::SeedableRng;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+use crate::simulation::{precision, Counts, ExactPass, Probability, Progress, SimulateError, SimulationRng, Setup};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Running,
+    Paused,
+    Cancelled,
+    /// Every possible deal was played, more steps add nothing.
+    Exact,
+}
+
+/// A simulation of one query that is driven step by step. Every simulator keeps its own counts,
+/// so several can run side by side, e.g. to compare two trumps.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub struct Simulator {
+    setup: Setup,
+    rng: SimulationRng,
+    counts: Counts,
+    n_games: usize,
+    exact: Option<Vec<Probability>>,
+    // with few enough deals to play them all, the steps play those before any random game
+    exact_pass: Option<ExactPass>,
+    status: Status,
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+impl Simulator {
+    /// A simulator of `query`, see `query::parse_query`. The same query and seed give the same
+    /// probabilities after the same number of games.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen(constructor))]
+    pub fn new(query: String, seed: Option<u64>) -> Result<Simulator, SimulateError> {
+        let setup = Setup::new(&query)?;
+        Ok(Simulator {
+            counts: setup.counts(),
+            exact_pass: setup.exact_pass(),
+            setup,
+            rng: SimulationRng::seed_from_u64(seed.unwrap_or_else(rand::random)),
+            n_games: 0,
+            exact: None,
+            status: Status::Running,
+        })
+    }
+
+    /// Play up to `n_games` more random games and return how many were played. Nothing is played
+    /// when paused, cancelled or when every possible deal was played already. When there are few
+    /// enough deals, the steps play about `n_games` games of every possible deal instead and
+    /// return 0, until the last deal was played.
+    pub fn step(&mut self, n_games: usize) -> Result<usize, SimulateError> {
+        if self.status != Status::Running {
+            return Ok(0);
+        }
+        if let Some(pass) = &mut self.exact_pass {
+            self.setup.play_exact(pass, n_games, &mut self.rng);
+            if let Some(probabilities) = self.setup.exact_probabilities(pass)? {
+                self.exact = Some(probabilities);
+                self.exact_pass = None;
+                self.status = Status::Exact;
+            }
+            return Ok(0);
+        }
+        self.setup.play(n_games, &mut self.rng, &mut self.counts);
+        self.n_games += n_games;
+        Ok(n_games)
+    }
+
+    /// The number of random games played so far.
+    pub fn games(&self) -> usize {
+        self.n_games
+    }
+
+    /// How far the true percentages may lie from the ones so far, see `simulation::precision`.
+    pub fn precision(&self) -> f64 {
+        precision(&self.probabilities())
+    }
+
+    /// Stop playing games until `resume` is called.
+    pub fn pause(&mut self) {
+        if self.status == Status::Running {
+            self.status = Status::Paused;
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.status == Status::Paused {
+            self.status = Status::Running;
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.status == Status::Paused
+    }
+
+    /// Stop for good, the probabilities so far can still be taken.
+    pub fn cancel(&mut self) {
+        if self.status != Status::Exact {
+            self.status = Status::Cancelled;
+        }
+    }
+
+    /// Whether more steps will not play any game, because the simulator was cancelled or every
+    /// possible deal was played.
+    pub fn is_done(&self) -> bool {
+        matches!(self.status, Status::Cancelled | Status::Exact)
+    }
+}
+
+impl Simulator {
+    /// The probabilities of the games played so far.
+    pub fn probabilities(&self) -> Vec<Probability> {
+        match &self.exact {
+            Some(probabilities) => probabilities.clone(),
+            None => self.setup.probabilities(&self.counts),
+        }
+    }
+
+    /// The probabilities of the games played so far with their precision.
+    pub fn progress(&self) -> Progress {
+        Progress::new(self.probabilities())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+impl Simulator {
+    /// The probabilities of the games played so far with their precision.
+    pub fn snapshot(&self) -> Result<JsValue, SimulateError> {
+        self.progress().to_js_value()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::simulation::{counts, games_counted, SimulateError};
+    use crate::simulator::Simulator;
+
+    #[test]
+    fn test_simulators_are_independent() {
+        let mut hearts = Simulator::new("4p h 2s 4s AH".to_string(), Some(42)).unwrap();
+        let mut clubs = Simulator::new("4p c 2s 4s AH".to_string(), Some(42)).unwrap();
+        assert_eq!(100, hearts.step(100).unwrap());
+        clubs.step(30).unwrap();
+        hearts.step(100).unwrap();
+        assert_eq!((200, 30), (hearts.games(), clubs.games()));
+
+        // taking steps in other sizes gives the same result
+        let mut again = Simulator::new("4p h 2s 4s AH".to_string(), Some(42)).unwrap();
+        again.step(150).unwrap();
+        again.step(50).unwrap();
+        assert_eq!(counts(&hearts.probabilities()), counts(&again.probabilities()));
+    }
+
+    #[test]
+    fn test_pause_and_cancel() {
+        let mut simulator = Simulator::new("3p x KD QS 2C".to_string(), Some(1)).unwrap();
+        simulator.step(10).unwrap();
+        simulator.pause();
+        assert!(simulator.is_paused());
+        assert_eq!(0, simulator.step(10).unwrap());
+        simulator.resume();
+        assert_eq!(10, simulator.step(10).unwrap());
+
+        simulator.cancel();
+        assert!(simulator.is_done());
+        assert_eq!(0, simulator.step(10).unwrap());
+        simulator.resume();
+        assert_eq!(0, simulator.step(10).unwrap());
+        assert_eq!(20, games_counted(&counts(&simulator.probabilities()), 3));
+    }
+
+    #[test]
+    fn test_exact_is_spread_over_steps() {
+        // every deal is played in 4614 games
+        let mut simulator = Simulator::new("3p x AS".to_string(), Some(1)).unwrap();
+        assert_eq!(0, simulator.step(1000).unwrap());
+        assert!(!simulator.is_done());
+        simulator.pause();
+        simulator.step(1000).unwrap();
+        simulator.resume();
+        let mut n_steps = 1;
+        while !simulator.is_done() {
+            assert_eq!(0, simulator.step(1000).unwrap());
+            n_steps += 1;
+        }
+        assert_eq!(5, n_steps);
+        assert_eq!(0, simulator.games());
+        assert!(simulator.probabilities().iter().all(|p| p.exact));
+        assert_eq!(0.0, simulator.precision());
+    }
+
+    #[test]
+    fn test_precision_improves_with_more_games() {
+        let mut simulator = Simulator::new("4p h 2s 4s AH".to_string(), Some(3)).unwrap();
+        assert_eq!(100.0, simulator.precision());
+        simulator.step(100).unwrap();
+        let rough = simulator.precision();
+        simulator.step(900).unwrap();
+        assert!(simulator.precision() < rough);
+        assert_eq!(simulator.precision(), simulator.progress().precision);
+        assert!(simulator.probabilities().iter().all(|p| p.lower <= p.percentage && p.percentage <= p.upper));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Some(SimulateError::MissingTrump { position: 2 }), Simulator::new("4p".to_string(), None).err());
+        assert_eq!(Some(SimulateError::NoConsistentDeal), Simulator::new("3p h KS p1.void=s,h,d,c".to_string(), Some(1)).err());
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use serde::Serialize;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+use crate::card::{Card, Suit};
+use crate::query::{parse_card, parse_trump, tokenize, Token};
+use crate::simulation::SimulateError;
+
+/// The tricks a player takes when everyone plays perfectly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TrickRange {
+    /// The fewest tricks the player can hold themselves to when the others try to give them tricks.
+    pub min: usize,
+    /// The most tricks the player can take when the others try to stop them.
+    pub max: usize,
+}
+
+// cards are bits of a u64 like in a `CardSet`, 13 bits per suit in the order of `Suit::ALL`
+fn card_bit(card: &Card) -> u64 {
+    1 << card.index()
+}
+
+fn suit_mask(suit: usize) -> u64 {
+    0x1fff << (suit * 13)
+}
+
+struct Search {
+    n_players: usize,
+    trump: Option<usize>,
+    target: usize,
+    // whether the target tries to take as many tricks as possible, or as few
+    maximize: bool,
+    hands: Vec<u64>,
+    // bounds on the tricks the target takes from a trick boundary on, by hands and leader
+    table: HashMap<(Vec<u64>, usize), (usize, usize)>,
+}
+
+impl Search {
+    fn winner(&self, trick: &[(usize, usize)]) -> usize {
+        let led = trick[0].1 / 13;
+        let rank = |card: usize| match card / 13 {
+            suit if Some(suit) == self.trump => 20 + card % 13,
+            suit if suit == led => 1 + card % 13,
+            _ => 0,
+        };
+        trick.iter().max_by_key(|&&(_, card)| rank(card)).unwrap().0
+    }
+
+    // legal cards of the player, of a run of cards that are equal because no other remaining card
+    // lies between them only the highest is returned
+    fn moves(&self, player: usize, trick: &[(usize, usize)]) -> Vec<usize> {
+        let hand = self.hands[player];
+        let legal = match trick.first() {
+            Some(&(_, led)) if hand & suit_mask(led / 13) != 0 => hand & suit_mask(led / 13),
+            _ => hand,
+        };
+        let others = self.hands.iter().enumerate()
+            .filter(|&(p, _)| p != player)
+            .fold(0, |mask, (_, &h)| mask | h)
+            | trick.iter().fold(0, |mask, &(_, card)| mask | 1 << card);
+
+        let mut moves = Vec::new();
+        for card in (0..52).filter(|&card| legal & (1 << card) != 0) {
+            let next = (card + 1..(card / 13 + 1) * 13).find(|&c| (hand | others) & (1 << c) != 0);
+            if next.is_none_or(|c| legal & (1 << c) == 0) {
+                moves.push(card);
+            }
+        }
+        // high cards first, they decide most tricks
+        moves.reverse();
+        moves
+    }
+
+    // the tricks the target takes from here, within the window alpha..beta
+    fn search(&mut self, leader: usize, trick: &mut Vec<(usize, usize)>, mut alpha: usize, mut beta: usize) -> usize {
+        let remaining = self.hands[leader].count_ones() as usize + usize::from(!trick.is_empty());
+        if remaining == 0 {
+            return 0;
+        }
+
+        let key = trick.is_empty().then(|| (self.hands.clone(), leader));
+        if let Some(&(lower, upper)) = key.as_ref().and_then(|key| self.table.get(key)) {
+            if lower >= beta || lower == upper {
+                return lower;
+            }
+            if upper <= alpha {
+                return upper;
+            }
+            alpha = alpha.max(lower);
+            beta = beta.min(upper);
+        }
+        let (alpha_start, beta_start) = (alpha, beta);
+
+        let player = (leader + trick.len()) % self.n_players;
+        let maximizing = (player == self.target) == self.maximize;
+        let mut best = if maximizing { 0 } else { remaining };
+        let mut cutoff = false;
+        for card in self.moves(player, trick) {
+            self.hands[player] &= !(1 << card);
+            trick.push((player, card));
+
+            let value = if trick.len() == self.n_players {
+                let winner = self.winner(trick);
+                let won = usize::from(winner == self.target);
+                if beta <= won {
+                    // the target takes at least `won`, already enough for a cutoff
+                    won
+                } else {
+                    let saved = std::mem::take(trick);
+                    let value = won + self.search(winner, &mut Vec::with_capacity(self.n_players), alpha.saturating_sub(won), beta - won);
+                    *trick = saved;
+                    value
+                }
+            } else {
+                self.search(leader, trick, alpha, beta)
+            };
+
+            trick.pop();
+            self.hands[player] |= 1 << card;
+
+            if maximizing {
+                best = best.max(value);
+                alpha = alpha.max(best);
+            } else {
+                best = best.min(value);
+                beta = beta.min(best);
+            }
+            if alpha >= beta {
+                cutoff = true;
+                break;
+            }
+        }
+
+        if let Some(key) = key {
+            let (lower, upper) = self.table.get(&key).copied().unwrap_or((0, remaining));
+            // after a cutoff only one side is known, without one every move was searched and the
+            // value is exact unless it lies outside the window
+            let bounds = match (cutoff, maximizing) {
+                (true, true) => (lower.max(best), upper),
+                (true, false) => (lower, upper.min(best)),
+                (false, true) if best <= alpha_start => (lower, upper.min(best)),
+                (false, false) if best >= beta_start => (lower.max(best), upper),
+                (false, _) => (best, best),
+            };
+            self.table.insert(key, bounds);
+        }
+        best
+    }
+}
+
+/// The tricks every player takes when all hands are known and everyone plays perfectly.
+/// `hands[i]` are the cards of player i, `leader` leads the first trick and the others follow in
+/// order of their index.
+pub fn solve(hands: &[Vec<Card>], trump: Option<Suit>, leader: usize) -> Result<Vec<TrickRange>, SimulateError> {
+    let n_players = hands.len();
+    if leader >= n_players {
+        return Err(SimulateError::InvalidLeader { leader, n_players });
+    }
+    if hands.iter().any(|hand| hand.len() != hands[0].len()) {
+        return Err(SimulateError::UnequalHands { sizes: hands.iter().map(|hand| hand.len()).collect() });
+    }
+    let mut masks = Vec::with_capacity(n_players);
+    let mut seen = 0;
+    for hand in hands {
+        let mut mask = 0;
+        for card in hand {
+            if seen & card_bit(card) != 0 {
+                return Err(SimulateError::CardInTwoHands { card: *card });
+            }
+            seen |= card_bit(card);
+            mask |= card_bit(card);
+        }
+        masks.push(mask);
+    }
+
+    let trump = trump.map(|trump| trump as usize);
+    let n_tricks = hands[0].len();
+    Ok((0..n_players).map(|target| {
+        let mut range = [true, false].map(|maximize| {
+            let mut search = Search { n_players, trump, target, maximize, hands: masks.clone(), table: HashMap::new() };
+            search.search(leader, &mut Vec::with_capacity(n_players), 0, n_tricks)
+        });
+        range.swap(0, 1);
+        TrickRange { min: range[0], max: range[1] }
+    }).collect())
+}
+
+/// Parse hands like `"As 2h"` and a trump like `"h"`, or `"x"` for no trump. Error positions are
+/// character positions within the hand.
+pub fn parse_deal(hands: &[String], trump: &str) -> Result<(Vec<Vec<Card>>, Option<Suit>), SimulateError> {
+    let trump = parse_trump(Token { position: 0, text: trump })?;
+    let mut cards: Vec<Vec<Card>> = Vec::with_capacity(hands.len());
+    for hand in hands {
+        let mut parsed = Vec::new();
+        for token in tokenize(hand) {
+            let seen: Vec<&[Card]> = cards.iter().map(|hand| hand.as_slice()).chain([parsed.as_slice()]).collect();
+            let card = parse_card(token, &seen)?;
+            parsed.push(card);
+        }
+        cards.push(parsed);
+    }
+    Ok((cards, trump))
+}
+
+/// Solve a deal of known hands, see `solve`. Returns the trick range of every player.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn solve_deal(hands: Vec<String>, trump: String, leader: usize) -> Result<JsValue, SimulateError> {
+    let (hands, trump) = parse_deal(&hands, &trump)?;
+    Ok(serde_wasm_bindgen::to_value(&solve(&hands, trump, leader)?)?)
+}
+
+#[cfg(test)]
+mod test {
+    use rand::prelude::SliceRandom;
+    use rand::{Rng, SeedableRng};
+    use crate::card::{card, create_deck, create_hand_from_string, highest_card, Card, Suit};
+    use crate::simulation::{SimulateError, SimulationRng};
+    use crate::solver::{parse_deal, solve, TrickRange};
+
+    fn hands(hands: &[&str]) -> Vec<Vec<Card>> {
+        hands.iter().map(|hand| create_hand_from_string(hand).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_single_trick() {
+        let result = solve(&hands(&["As", "Ks", "2h"]), Some(Suit::Hearts), 0).unwrap();
+        assert_eq!(vec![TrickRange { min: 0, max: 0 }, TrickRange { min: 0, max: 0 }, TrickRange { min: 1, max: 1 }], result);
+    }
+
+    // every legal play is tried, the target maximizes or minimizes its tricks and the others do the opposite
+    fn brute_force(hands: &mut Vec<Vec<Card>>, trick: &mut Vec<(usize, Card)>, trump: Option<Suit>, leader: usize, target: usize, maximize: bool) -> usize {
+        let n_players = hands.len();
+        if trick.len() == n_players {
+            let cards: Vec<Card> = trick.iter().map(|(_, card)| *card).collect();
+            let winner = trick[highest_card(&cards, trump).unwrap().0].0;
+            let saved = std::mem::take(trick);
+            let tricks = usize::from(winner == target) + brute_force(hands, trick, trump, winner, target, maximize);
+            *trick = saved;
+            return tricks;
+        }
+        let player = (leader + trick.len()) % n_players;
+        if hands[player].is_empty() {
+            return 0;
+        }
+
+        let legal: Vec<usize> = match trick.first() {
+            Some((_, led)) if hands[player].iter().any(|card| card.suit() == led.suit()) => {
+                (0..hands[player].len()).filter(|&i| hands[player][i].suit() == led.suit()).collect()
+            }
+            _ => (0..hands[player].len()).collect(),
+        };
+        let values = legal.into_iter().map(|i| {
+            let card = hands[player].remove(i);
+            trick.push((player, card));
+            let value = brute_force(hands, trick, trump, leader, target, maximize);
+            trick.pop();
+            hands[player].insert(i, card);
+            value
+        });
+        if (player == target) == maximize { values.max().unwrap() } else { values.min().unwrap() }
+    }
+
+    #[test]
+    fn test_against_brute_force() {
+        let mut rng = SimulationRng::seed_from_u64(5);
+        for (n_players, n_cards) in [(2, 4), (3, 3), (4, 3), (3, 4), (5, 2), (3, 5)] {
+            for _ in 0..5 {
+                let mut deck = create_deck(&[]);
+                deck.shuffle(&mut rng);
+                let mut hands: Vec<Vec<Card>> = deck.chunks(n_cards).take(n_players).map(|hand| hand.to_vec()).collect();
+                let trump = [None, Some(Suit::Hearts)][rng.gen_range(0..2)];
+                let leader = rng.gen_range(0..n_players);
+
+                let result = solve(&hands, trump, leader).unwrap();
+                for (target, range) in result.iter().enumerate() {
+                    let max = brute_force(&mut hands, &mut Vec::new(), trump, leader, target, true);
+                    let min = brute_force(&mut hands, &mut Vec::new(), trump, leader, target, false);
+                    assert_eq!(TrickRange { min, max }, *range, "{:?} {:?} {}", hands, trump, leader);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_top_trumps() {
+        let result = solve(&hands(&["As Ks Qs Js", "Ah Kh Qh Jh", "Ad Kd Qd Jd", "2c 3c 4c 5c"]), Some(Suit::Spades), 3).unwrap();
+        assert_eq!(TrickRange { min: 4, max: 4 }, result[0]);
+        assert!(result.iter().skip(1).all(|range| *range == TrickRange { min: 0, max: 0 }));
+    }
+
+    #[test]
+    fn test_parse_deal() {
+        let deal = |hands: &[&str], trump: &str| parse_deal(&hands.iter().map(|hand| hand.to_string()).collect::<Vec<_>>(), trump);
+        assert_eq!(Ok((hands(&["As 2h", "Ks 3h"]), Some(Suit::Hearts))), deal(&["As 2h", "Ks 3h"], "h"));
+        assert_eq!(Err(SimulateError::DuplicateCard { position: 3, card: card("2h") }), deal(&["As 2h", "Ks 2H"], "x"));
+        assert_eq!(Err(SimulateError::InvalidCard { position: 0, token: "1s".to_string() }), deal(&["1s"], "x"));
+        assert_eq!(Err(SimulateError::InvalidTrump { position: 0, token: "q".to_string() }), deal(&["As"], "q"));
+    }
+
+    #[test]
+    fn test_invalid_deals() {
+        assert_eq!(Err(SimulateError::UnequalHands { sizes: vec![2, 1] }), solve(&hands(&["As Ah", "2s"]), None, 0));
+        assert_eq!(Err(SimulateError::InvalidLeader { leader: 2, n_players: 2 }), solve(&hands(&["As", "2s"]), None, 2));
+        assert_eq!(Err(SimulateError::CardInTwoHands { card: card("As") }), solve(&hands(&["As", "AS"]), None, 0));
+    }
+}
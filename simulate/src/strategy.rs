@@ -0,0 +1,467 @@
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+use std::sync::Arc;
+use rand::{Rng, RngCore};
+use crate::card::{highest_card, Card, CardSet, Hand, Suit};
+
+/// What a player knows when it is their turn in a trick.
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerView<'a> {
+    pub hand: &'a Hand,
+    /// Cards played to the current trick so far, the first one was led.
+    pub trick: &'a [Card],
+    pub trump: Option<Suit>,
+    /// Tricks won so far this round.
+    pub tricks: usize,
+    pub bid: Option<usize>,
+}
+
+impl PlayerView<'_> {
+    /// The cards that may be played: the led suit when the player has it.
+    pub fn legal(&self) -> CardSet {
+        let Some(led) = self.trick.first() else {
+            return self.hand.set();
+        };
+        match self.hand.set().intersection(CardSet::of_suit(led.suit())) {
+            suited if suited.is_empty() => self.hand.set(),
+            suited => suited,
+        }
+    }
+
+    /// Indices in the hand of the cards that may be played.
+    pub fn legal_cards(&self) -> impl Iterator<Item = usize> + Clone + '_ {
+        let legal = self.legal();
+        self.hand.indices().filter(move |&i| legal.contains(self.hand.get(i)))
+    }
+
+    /// The cards that would win the trick as it stands.
+    pub fn winners(&self) -> CardSet {
+        match highest_card(self.trick, self.trump) {
+            Some((_, &best)) => match self.trump {
+                Some(trump) if best.suit() != trump => CardSet::above(best).union(CardSet::of_suit(trump)),
+                _ => CardSet::above(best),
+            },
+            None => CardSet::DECK,
+        }
+    }
+
+    /// Whether the card would win the trick as it stands.
+    pub fn wins(&self, index: usize) -> bool {
+        self.winners().contains(self.hand.get(index))
+    }
+
+    fn trumps(&self) -> CardSet {
+        self.trump.map_or(CardSet::EMPTY, CardSet::of_suit)
+    }
+
+    // trumps are stronger than any other suit, of equally strong cards the first in hand is the
+    // weakest and the last in hand the strongest
+    fn lowest(&self, cards: CardSet) -> Option<usize> {
+        let others = cards.difference(self.trumps());
+        let weakest = match others.lowest_rank() {
+            Some(rank) => others.intersection(CardSet::of_rank(rank)),
+            None => cards.first().map_or(CardSet::EMPTY, CardSet::from),
+        };
+        self.hand.first_of(weakest)
+    }
+
+    fn highest(&self, cards: CardSet) -> Option<usize> {
+        let strongest = match cards.intersection(self.trumps()).last() {
+            Some(trump) => CardSet::from(trump),
+            None => cards.intersection(CardSet::of_rank(cards.highest_rank()?)),
+        };
+        self.hand.last_of(strongest)
+    }
+}
+
+/// What a player knows when it is their turn to bid.
+#[derive(Debug, Clone, Copy)]
+pub struct BidView<'a> {
+    pub hand: &'a Hand,
+    /// The tricks `estimate_tricks` expects the hand to take.
+    pub estimate: f64,
+    pub trump: Option<Suit>,
+    pub n_players: usize,
+    /// Cards every player was dealt this round.
+    pub n_cards: usize,
+    /// Tricks won so far this round, they count towards the bid.
+    pub tricks: usize,
+    /// Bids of the players before this one, starting with the player left of the dealer. `None`
+    /// for players whose strategy does not bid.
+    pub previous_bids: &'a [Option<usize>],
+}
+
+impl BidView<'_> {
+    /// The bid the dealer may not make: the one that makes the bids add up to the number of cards.
+    /// `None` for the other players, or when a bid before the dealer is unknown.
+    pub fn forbidden_bid(&self) -> Option<usize> {
+        if self.previous_bids.len() + 1 != self.n_players {
+            return None;
+        }
+        let taken: usize = self.previous_bids.iter().copied().sum::<Option<usize>>()?;
+        self.n_cards.checked_sub(taken)
+    }
+}
+
+/// How a player picks a card to play, and optionally what they bid.
+pub trait Strategy: Debug + Send + Sync {
+    /// Index in `view.hand` of the card to play, see `Hand::get`. Only called with cards in hand.
+    fn choose_card(&self, view: &PlayerView, rng: &mut dyn RngCore) -> usize;
+
+    /// The number of tricks the player aims for with this hand on top of `view.tricks`, if the
+    /// strategy bids.
+    fn bid(&self, _view: &BidView) -> Option<usize> {
+        None
+    }
+
+    /// Whether the strategy uses the random number generator, so the same deal can be played
+    /// differently.
+    fn is_random(&self) -> bool {
+        false
+    }
+}
+
+/// The original heuristic: lead the last card in hand, follow with the first card of the led
+/// suit and only overtrump when a trump was already played. Once its bid is made it no longer
+/// trumps in. Bids like `BidAware`.
+#[derive(Debug, Clone, Copy)]
+pub struct Classic;
+
+impl Strategy for Classic {
+    fn choose_card(&self, view: &PlayerView, _rng: &mut dyn RngCore) -> usize {
+        let last = view.hand.last().unwrap();
+        let Some(led) = view.trick.first() else {
+            return last;
+        };
+
+        if let Some(index) = view.hand.first_of_suit(led.suit()) {
+            return index;
+        }
+
+        match view.trump {
+            Some(trump) if led.suit() != trump && view.bid.is_some_and(|bid| view.tricks >= bid) => {
+                view.hand.first_of(CardSet::DECK.difference(CardSet::of_suit(trump))).unwrap_or(last)
+            }
+            Some(trump) if led.suit() != trump => match view.trick.iter().filter(|card| card.suit() == trump).max() {
+                Some(&highest_trump) => view.hand.first_of(CardSet::above(highest_trump))
+                    .or_else(|| view.hand.first_of(CardSet::DECK.difference(CardSet::of_suit(trump))))
+                    .or_else(|| view.hand.first())
+                    .unwrap(),
+                None => last,
+            },
+            _ => last,
+        }
+    }
+
+    fn bid(&self, view: &BidView) -> Option<usize> {
+        Some(estimate_bid(view))
+    }
+}
+
+/// Any legal card, uniformly at random.
+#[derive(Debug, Clone, Copy)]
+pub struct RandomLegal;
+
+impl Strategy for RandomLegal {
+    fn choose_card(&self, view: &PlayerView, rng: &mut dyn RngCore) -> usize {
+        let n_legal = view.legal().len();
+        view.legal_cards().nth(rng.gen_range(0..n_legal)).unwrap()
+    }
+
+    fn is_random(&self) -> bool {
+        true
+    }
+}
+
+/// Always the strongest legal card.
+#[derive(Debug, Clone, Copy)]
+pub struct GreedyHighest;
+
+impl Strategy for GreedyHighest {
+    fn choose_card(&self, view: &PlayerView, _rng: &mut dyn RngCore) -> usize {
+        view.highest(view.legal()).unwrap()
+    }
+}
+
+/// The weakest legal card that takes the trick, or the weakest card when none does.
+#[derive(Debug, Clone, Copy)]
+pub struct LowestThatWins;
+
+impl Strategy for LowestThatWins {
+    fn choose_card(&self, view: &PlayerView, _rng: &mut dyn RngCore) -> usize {
+        let legal = view.legal();
+        view.lowest(legal.intersection(view.winners()))
+            .or_else(|| view.lowest(legal))
+            .unwrap()
+    }
+}
+
+/// Wins tricks until its bid is reached and ducks after that. Bids the tricks it expects from
+/// its high cards and trumps, as dealer one more or one less when that bid is forbidden.
+#[derive(Debug, Clone, Copy)]
+pub struct BidAware;
+
+impl Strategy for BidAware {
+    fn choose_card(&self, view: &PlayerView, rng: &mut dyn RngCore) -> usize {
+        let legal = view.legal();
+        if view.tricks < view.bid.unwrap_or(0) {
+            if view.trick.is_empty() {
+                return view.highest(legal).unwrap();
+            }
+            return LowestThatWins.choose_card(view, rng);
+        }
+
+        // the strongest card that still loses, otherwise the weakest card. Every card wins an
+        // empty trick.
+        view.highest(legal.difference(view.winners()))
+            .or_else(|| view.lowest(legal))
+            .unwrap()
+    }
+
+    fn bid(&self, view: &BidView) -> Option<usize> {
+        Some(estimate_bid(view))
+    }
+}
+
+// the estimated tricks of the hand rounded, or when the dealer may not bid that, rounded the
+// other way
+fn estimate_bid(view: &BidView) -> usize {
+    // estimates are never negative, so adding a half and truncating rounds them, without the call
+    // to the C library that `f64::round` is on most targets
+    let bid = ((view.estimate + 0.5) as usize).min(view.hand.len());
+    match view.forbidden_bid() {
+        Some(forbidden) if bid + view.tricks == forbidden => {
+            if bid == 0 || (view.estimate > bid as f64 && bid < view.hand.len()) { bid + 1 } else { bid - 1 }
+        }
+        _ => bid,
+    }
+}
+
+/// Expected number of tricks of a hand. High trumps count as tricks, long trumps often make one.
+/// Other high cards need enough cards of their suit to survive the aces above them and lose
+/// value as more players can trump them.
+pub fn estimate_tricks(hand: CardSet, trump: Option<Suit>, n_players: usize) -> f64 {
+    use crate::card::Rank;
+    let crowd = (4.0 / n_players as f64).min(1.0);
+
+    // suit by suit, a hand holds few of the cards that count
+    Suit::ALL.iter().map(|&suit| {
+        let suited = hand.intersection(CardSet::of_suit(suit));
+        let length = suited.len();
+        let has = |rank| if suited.contains(Card::new(suit, rank)) { 1.0 } else { 0.0 };
+        if Some(suit) == trump {
+            let honours = has(Rank::Ace) + 0.9 * has(Rank::King) + 0.7 * has(Rank::Queen) + 0.5 * has(Rank::Jack);
+            let low = suited.difference(CardSet::above(Card::new(suit, Rank::Ten))).len() as f64;
+            honours + low * if length >= 3 { 0.3 } else { 0.15 }
+        } else {
+            let king = if length >= 2 { 0.6 } else { 0.3 };
+            let queen = if length >= 3 { 0.3 } else { 0.0 };
+            crowd * (0.9 * has(Rank::Ace) + king * has(Rank::King) + queen * has(Rank::Queen))
+        }
+    }).sum()
+}
+
+/// The strategies a simulation query can pick for the opponents, e.g. `vs=random`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StrategyKind {
+    #[default]
+    Classic,
+    RandomLegal,
+    GreedyHighest,
+    LowestThatWins,
+    BidAware,
+}
+
+impl StrategyKind {
+    pub fn strategy(&self) -> Arc<dyn Strategy> {
+        match self {
+            StrategyKind::Classic => Arc::new(Classic),
+            StrategyKind::RandomLegal => Arc::new(RandomLegal),
+            StrategyKind::GreedyHighest => Arc::new(GreedyHighest),
+            StrategyKind::LowestThatWins => Arc::new(LowestThatWins),
+            StrategyKind::BidAware => Arc::new(BidAware),
+        }
+    }
+}
+
+impl FromStr for StrategyKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "classic" => Ok(StrategyKind::Classic),
+            "random" => Ok(StrategyKind::RandomLegal),
+            "greedy" => Ok(StrategyKind::GreedyHighest),
+            "lowest" => Ok(StrategyKind::LowestThatWins),
+            "bid" => Ok(StrategyKind::BidAware),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for StrategyKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            StrategyKind::Classic => "classic",
+            StrategyKind::RandomLegal => "random",
+            StrategyKind::GreedyHighest => "greedy",
+            StrategyKind::LowestThatWins => "lowest",
+            StrategyKind::BidAware => "bid",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+    use crate::card::{card, Card, CardSet, Hand, Suit};
+    use crate::game::{Game, RoundState};
+    use crate::simulation::SimulationRng;
+    use crate::strategy::{estimate_tricks, BidAware, BidView, Classic, GreedyHighest, LowestThatWins, PlayerView, RandomLegal, Strategy};
+
+    fn cards(s: &str) -> Vec<Card> {
+        s.split_whitespace().map(card).collect()
+    }
+
+    fn set(s: &str) -> CardSet {
+        cards(s).iter().collect()
+    }
+
+    fn choose(strategy: &dyn Strategy, hand: &[Card], trick: &[Card], tricks: usize, bid: Option<usize>) -> Card {
+        let hand = Hand::from(hand);
+        let view = PlayerView { hand: &hand, trick, trump: Some(Suit::Hearts), tricks, bid };
+        let mut rng = SimulationRng::seed_from_u64(1);
+        hand.get(strategy.choose_card(&view, &mut rng))
+    }
+
+    #[test]
+    fn test_legal_cards() {
+        let hand = Hand::from(&cards("2s Ks 3h Ad")[..]);
+        let view = PlayerView { hand: &hand, trick: &cards("10s"), trump: Some(Suit::Hearts), tricks: 0, bid: None };
+        assert_eq!(vec![0, 1], view.legal_cards().collect::<Vec<_>>());
+        let view = PlayerView { trick: &cards("10c"), ..view };
+        assert_eq!(vec![0, 1, 2, 3], view.legal_cards().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_strategies_follow_suit() {
+        let hand = cards("2s Ks 3h Ad");
+        let trick = cards("10s");
+        let strategies: [&dyn Strategy; 5] = [&Classic, &RandomLegal, &GreedyHighest, &LowestThatWins, &BidAware];
+        for strategy in strategies {
+            for bid in [None, Some(0), Some(2)] {
+                let card = choose(strategy, &hand, &trick, 0, bid);
+                assert_eq!(Suit::Spades, card.suit(), "{:?}", strategy);
+            }
+        }
+    }
+
+    #[test]
+    fn test_greedy_and_lowest() {
+        let hand = cards("2s Ks 3h Ad");
+        assert_eq!(card("3h"), choose(&GreedyHighest, &hand, &[], 0, None));
+        assert_eq!(card("Ks"), choose(&GreedyHighest, &hand, &cards("10s"), 0, None));
+        assert_eq!(card("Ks"), choose(&LowestThatWins, &hand, &cards("10s"), 0, None));
+        assert_eq!(card("2s"), choose(&LowestThatWins, &hand, &cards("As"), 0, None));
+        // void in clubs, the trump wins
+        assert_eq!(card("3h"), choose(&LowestThatWins, &hand, &cards("Ac"), 0, None));
+    }
+
+    #[test]
+    fn test_bid_aware() {
+        let hand = cards("2s Ks 3h Ad");
+        // still needs a trick
+        assert_eq!(card("Ks"), choose(&BidAware, &hand, &cards("10s"), 0, Some(1)));
+        assert_eq!(card("3h"), choose(&BidAware, &hand, &[], 0, Some(1)));
+        // bid reached, duck under the ten
+        assert_eq!(card("2s"), choose(&BidAware, &hand, &cards("10s"), 1, Some(1)));
+        assert_eq!(card("Ad"), choose(&BidAware, &hand, &cards("Ac"), 1, Some(1)));
+        let estimate = estimate_tricks(set("2s Ks 3h Ad"), Some(Suit::Diamonds), 4);
+        let view = BidView { hand: &Hand::from(&hand[..]), estimate, trump: Some(Suit::Diamonds), n_players: 4, n_cards: 4, tricks: 0, previous_bids: &[] };
+        assert_eq!(Some(2), BidAware.bid(&view));
+    }
+
+    #[test]
+    fn test_dealer_avoids_forbidden_bid() {
+        let hand = Hand::from(&cards("2s Ks 3h Ad")[..]);
+        let estimate = estimate_tricks(hand.set(), Some(Suit::Diamonds), 3);
+        let view = BidView { hand: &hand, estimate, trump: Some(Suit::Diamonds), n_players: 3, n_cards: 4, tricks: 0, previous_bids: &[Some(1), Some(1)] };
+        assert_eq!(Some(2), view.forbidden_bid());
+        // 2.1 tricks expected, 2 is forbidden
+        assert_eq!(Some(1), BidAware.bid(&view));
+        assert_eq!(Some(1), Classic.bid(&view));
+
+        let view = BidView { previous_bids: &[Some(1), Some(2)], ..view };
+        assert_eq!(Some(2), BidAware.bid(&view));
+        // not the dealer, or a bid before the dealer is unknown
+        assert_eq!(None, BidView { previous_bids: &[Some(2)], ..view }.forbidden_bid());
+        assert_eq!(None, BidView { previous_bids: &[Some(2), None], ..view }.forbidden_bid());
+    }
+
+    #[test]
+    fn test_classic_stops_trumping_once_bid_is_made() {
+        let hand = cards("2s Ks 3h Ad");
+        assert_eq!(card("3h"), choose(&Classic, &hand, &cards("Ac 2h"), 0, Some(1)));
+        assert_eq!(card("3h"), choose(&Classic, &hand, &cards("Ac 2h"), 0, None));
+        assert_eq!(card("2s"), choose(&Classic, &hand, &cards("Ac 2h"), 1, Some(1)));
+        assert_eq!(card("2s"), choose(&Classic, &hand, &cards("Ac"), 0, Some(0)));
+    }
+
+    #[test]
+    fn test_bid_changes_classic_play() {
+        // player 1 leads a club, player 2 is void in clubs and trumps in until its bid is made
+        let hands = [cards("4c 5s"), cards("As 2c"), cards("3s 2h")];
+        let tricks = |bids: &'static [usize]| {
+            let mut rng = SimulationRng::seed_from_u64(1);
+            let mut game = Game::from_hands(Some(Suit::Hearts), &hands, &RoundState::default(), &Classic).with_known_bids(bids);
+            game.play_game(1, false, &mut rng);
+            game.players().iter().map(|player| player.tricks).collect::<Vec<_>>()
+        };
+        assert_eq!(vec![0, 1, 1], tricks(&[0, 1]));
+        // with its bid made player 2 keeps the trump, which wins the spade trick instead of the ace
+        assert_eq!(vec![1, 0, 1], tricks(&[0, 0]));
+    }
+
+    #[test]
+    fn test_estimate_tricks() {
+        assert_eq!(0.0, estimate_tricks(set("2s 5h 7d"), Some(Suit::Clubs), 4));
+        assert!((estimate_tricks(set("Ac Kc Qc 2c"), Some(Suit::Clubs), 4) - 2.9).abs() < 1e-9);
+        // a bare king is worth less, and high cards are worth less against more players
+        assert!(estimate_tricks(set("Ks 2h"), None, 4) < estimate_tricks(set("Ks 2s"), None, 4));
+        assert!(estimate_tricks(set("As Ah"), None, 6) < estimate_tricks(set("As Ah"), None, 3));
+    }
+
+    #[test]
+    fn test_opponents_bid_before_playing() {
+        let mut rng = SimulationRng::seed_from_u64(3);
+        let mut game = Game::from_state(4, Some(Suit::Hearts), &mut rng, cards("2s Ks 3h"), &RoundState::default(), &BidAware).unwrap();
+        game.play_game(1, false, &mut rng);
+        assert_eq!(None, game.players()[0].bid);
+        assert!(game.players().iter().skip(1).all(|player| player.bid.is_some_and(|bid| bid <= 3)));
+
+        // the default strategy bids as well
+        let mut game = Game::from_state(4, Some(Suit::Hearts), &mut rng, cards("2s Ks 3h"), &RoundState::default(), &Classic).unwrap();
+        game.play_game(1, false, &mut rng);
+        assert_eq!(None, game.players()[0].bid);
+        assert!(game.players().iter().skip(1).all(|player| player.bid.is_some_and(|bid| bid <= 3)));
+
+        // player 1 bids first and player 2 second, their bids are known
+        let mut game = Game::from_state(4, Some(Suit::Hearts), &mut rng, cards("2s Ks 3h"), &RoundState::default(), &BidAware).unwrap()
+            .with_known_bids(&[3, 0]);
+        game.play_game(1, false, &mut rng);
+        assert_eq!((Some(3), Some(0)), (game.players()[1].bid, game.players()[2].bid));
+    }
+
+    #[test]
+    fn test_opponent_dealer_avoids_forbidden_bid() {
+        // player 0 would bid 2 and player 1 bids 0, so the dealer may not bid 0 with a hand that
+        // is worth nothing
+        let hands = [cards("As Ah"), cards("2s 2h"), cards("3s 3h")];
+        let mut rng = SimulationRng::seed_from_u64(1);
+        let mut game = Game::from_hands(None, &hands, &RoundState::default(), &BidAware);
+        game.play_game(0, false, &mut rng);
+        assert_eq!(None, game.players()[0].bid);
+        assert_eq!((Some(0), Some(1)), (game.players()[1].bid, game.players()[2].bid));
+    }
+}
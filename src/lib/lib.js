@@ -1,19 +1,46 @@
 import serializer_wasm from "../../serializer/Cargo.toml";
 import simulate_wasm from "../../simulate/Cargo.toml";
 
+let serializer;
+
+// Scores are calculated synchronously while the store updates, so the serializer is loaded once
+// before the app starts.
+export async function loadSerializer() {
+    if (serializer === undefined) {
+        serializer = await serializer_wasm();
+    }
+    return serializer;
+}
+
 export const GAME_VERSION = 3;
 export const CONFIG_VERSION = 1;
 
-export function initialGame(id, name) {
+export function initialGame(id, name, scoring = SCORING.STANDARD) {
     return {
         gameVersion: GAME_VERSION,
         id: id,
         name: name,
+        scoring: scoring,
         players: [],
         rounds: [],
     };
 }
 
+// Scoring variants by the name the serializer uses for them, the rules themselves live in serializer/src/score.rs
+export const SCORING = {
+    STANDARD: 'standard',
+    MISS_PENALTY: 'missPenalty',
+    TEN_PLUS_BID: 'tenPlusBid',
+    ZERO_BID_BONUS: 'zeroBidBonus',
+}
+
+export const SCORING_NAMES = {
+    [SCORING.STANDARD]: 'Standaard: 5 + bod, anders het aantal slagen',
+    [SCORING.MISS_PENALTY]: 'Strafpunten: 5 + bod, anders min het verschil',
+    [SCORING.TEN_PLUS_BID]: '10 + bod, anders niets',
+    [SCORING.ZERO_BID_BONUS]: 'Nulbonus: 0 geboden en gehaald geeft 5 + aantal kaarten',
+}
+
 export const initialRound = {
     nCards: 0,
     trump: 0,
@@ -36,82 +63,67 @@ export class LeaderboardEntry {
 
 export function currentRoundForGame(game) {
     let rounds = game.rounds;
-    let result = rounds.findIndex(round => round.tricks.length === 0)
+    let result = rounds.findIndex(round => !isRoundEntered(game, round))
     return result >= 0 ? result : rounds.length;
 }
 
-function comparePlayerScore(a, b) {
-    if (a.score !== b.score) {
-        return b.score - a.score;
-    } else if (a.nCorrectBids !== b.nCorrectBids) {
-        return b.nCorrectBids - a.nCorrectBids;
-    } else if (a.highestRoundTricks !== b.highestRoundTricks) {
-        return b.highestRoundTricks - a.highestRoundTricks;
-    } else {
-        return b.highestRoundScore - a.highestRoundScore;
-    }
+// Bids and tricks are entered per player, a shared game can hold a round that was only partly entered.
+// Missing entries are undefined after decoding and null after a round-trip through local storage.
+export function isEntered(value) {
+    return value !== undefined && value !== null;
 }
 
+// A round counts for the score once every player has a bid and tricks.
+export function isRoundEntered(game, round) {
+    return game.players.every(player => isEntered(round.bids?.[player.id]) && isEntered(round.tricks?.[player.id]));
+}
+
+// Leaderboard order as calculated by the serializer, including its tie-breaks.
+function compareLeaderboardPosition(a, b) {
+    return a.leaderBoardPosition - b.leaderBoardPosition;
+}
+
+// Scores are calculated by the serializer, the same scores the Rust tools report. Synchronous, so loadSerializer
+// must have finished first.
 export function calculateScoresForGame(game) {
+    if (serializer === undefined) {
+        throw new Error('serializer not loaded, await loadSerializer() before calculating scores');
+    }
+    applyScores(game, serializer.score(game));
+}
+
+// Copy the `GameScore` of the serializer into the players and rounds of a game.
+export function applyScores(game, scores) {
     game.players.forEach(player => {
-        player.score = 0;
-        player.highestRoundScore = 0;
-        player.nCorrectBids = 0;
-        player.highestRoundTricks = 0;
-        player.leaderBoardPosition = player.id + 1;
-        player.previousLeaderBoardPosition = undefined;
+        const playerScore = scores.players[player.id];
+        player.score = playerScore.score;
+        player.highestRoundScore = playerScore.highestRoundScore;
+        player.nCorrectBids = playerScore.nCorrectBids;
+        player.highestRoundTricks = playerScore.highestRoundTricks;
+        player.leaderBoardPosition = playerScore.leaderboardPosition;
+        player.previousLeaderBoardPosition = playerScore.previousLeaderboardPosition ?? undefined;
     });
 
-    game.rounds.forEach(round => {
-        if (!round.totalScore) {
-            round.totalScore = Array(game.players.length).fill(0);
-        }
-
-        if (round.bids && round.bids.length > 0 && round.tricks && round.tricks.length > 0) {
-            game.players.forEach(player => {
-                const bid = round.bids[player.id];
-                const tricks = round.tricks[player.id];
-                let score = player.score;
-
-                let roundScore;
-
-                if (bid === tricks) {
-                    roundScore = 5 + bid;
-                    player.nCorrectBids = player.nCorrectBids + 1;
-                } else {
-                    roundScore = tricks;
-                }
-                score += roundScore;
-
-                round.totalScore[player.id] = score;
-                if (roundScore > player.highestRoundScore) {
-                    player.highestRoundScore = roundScore;
-                }
-                if (tricks > player.highestRoundTricks) {
-                    player.highestRoundTricks = tricks;
-                }
-
-                player.score = score;
-            });
-            let standings = [...game.players].sort(comparePlayerScore);
-            standings.forEach((player, index) => {
-                player.previousLeaderBoardPosition = player.leaderBoardPosition;
-                player.leaderBoardPosition = index + 1;
-            });
+    game.rounds.forEach((round, index) => {
+        const roundScore = scores.rounds[index];
+        if (roundScore) {
+            round.totalScore = roundScore.totalScores;
+        } else {
+            delete round.totalScore;
         }
     });
 }
 
 export function isGameFinished(game) {
     // it is finished if all the rounds have bids and tricks
-    return game.rounds.every(round => round.bids && round.tricks && round.bids.length > 0 && round.tricks.length > 0);
+    return game.rounds.every(round => isRoundEntered(game, round));
 }
 
 function calculatePlayerPositions(game) {
     // returns a map with player names as keys and position as values (1-based)
 
     let players = [...game.players];
-    players.sort(comparePlayerScore);
+    players.sort(compareLeaderboardPosition);
     let result = []
     let currentScore = players[0].score;
     let currentPosition = 1;
@@ -241,14 +253,14 @@ export function calculateGameEarnings(game, allocationFn) {
 
 export function getWinners(game) {
     let players = [...game.players];
-    players.sort(comparePlayerScore);
+    players.sort(compareLeaderboardPosition);
     let highestScore = players[0].score;
     return players.filter(player => player.score === highestScore).map(player => player.name);
 }
 
 export function getLosers(game) {
     let players = [...game.players];
-    players.sort(comparePlayerScore);
+    players.sort(compareLeaderboardPosition);
     players.reverse();
     let lowestScore = players[0].score;
     return players.filter(player => player.score === lowestScore).map(player => player.name);
@@ -293,12 +305,52 @@ export const TRUMPS_SHORT_EN = {
 }
 
 export async function serializeGame(game) {
-    let serializer = await serializer_wasm();
+    await loadSerializer();
     return serializer.serialize(game);
 }
 
+export async function validateGame(game) {
+    await loadSerializer();
+    return serializer.validate(game);
+}
+
+// Readable description of a violation returned by validateGame, e.g. "Ronde 3, Anna: bod 11 is meer dan 10 kaarten".
+export function describeViolation(game, violation) {
+    let where = [];
+    if (violation.round !== undefined && violation.round !== null) {
+        where.push(`Ronde ${violation.round + 1}`);
+    }
+    if (violation.player !== undefined && violation.player !== null) {
+        where.push(game.players[violation.player]?.name ?? `speler ${violation.player + 1}`);
+    }
+
+    const rule = violation.rule;
+    let description;
+    switch (rule.kind) {
+        case 'noPlayers': description = 'spel heeft geen spelers'; break;
+        case 'tooManyPlayers': description = `te veel spelers (${rule.nPlayers})`; break;
+        case 'nameTooLong': description = `naam is te lang (${rule.nBytes} bytes)`; break;
+        case 'noRounds': description = 'spel heeft geen rondes'; break;
+        case 'tooManyRounds': description = `te veel rondes (${rule.nRounds})`; break;
+        case 'tooManyCards': description = `te veel kaarten (${rule.nCards})`; break;
+        case 'notEnoughCards': description = `niet genoeg kaarten om iedereen ${rule.nCards} te geven`; break;
+        case 'invalidTrump': description = `ongeldige troef ${rule.trump}`; break;
+        case 'invalidDealer': description = `deler ${rule.dealerId + 1} is geen speler`; break;
+        case 'dealerOutOfTurn': description = `deler is speler ${rule.dealerId + 1}, verwacht speler ${rule.expected + 1}`; break;
+        case 'wrongNumberOfBids': description = `${rule.nBids} biedingen passen niet bij het aantal spelers`; break;
+        case 'wrongNumberOfTricks': description = `${rule.nTricks} slagen passen niet bij het aantal spelers`; break;
+        case 'tricksWithoutBids': description = 'slagen ingevoerd zonder bod'; break;
+        case 'bidTooHigh': description = `bod ${rule.bid} is meer dan ${rule.nCards} kaarten`; break;
+        case 'tricksTooHigh': description = `${rule.tricks} slagen is meer dan ${rule.nCards} kaarten`; break;
+        case 'tricksDoNotAddUp': description = `slagen tellen op tot ${rule.total} in plaats van ${rule.nCards}`; break;
+        default: description = rule.kind;
+    }
+
+    return where.length > 0 ? `${where.join(', ')}: ${description}` : description;
+}
+
 export async function deserializeGame(serialized) {
-    let serializer = await serializer_wasm();
+    await loadSerializer();
     let game = serializer.deserialize(serialized);
     game.id = Date.now();
     calculateScoresForGame(game);
@@ -306,21 +358,61 @@ export async function deserializeGame(serialized) {
     return game;
 }
 
-export async function simulateGame(input, callback) {
+// Simulate `input` until the returned simulator is cancelled, or `maxGames` games are played,
+// calling `callback` with the progress, `{probabilities, precision}` (see `Progress` in
+// simulate/src/simulation.rs), every 250 ms and at the end. With `convergence`, e.g.
+// `{margin: 0.5, budgetMs: 60000}`, the simulation also ends when every percentage is within
+// ±margin or the time budget runs out. The simulator can be paused, resumed and cancelled until
+// `finished` resolves to the final probabilities, after which it is freed. Every call gets its
+// own simulator, so several simulations can run side by side. Pass a seed and a number of games
+// to get the same result on every run.
+export async function simulateGame(input, callback, seed, maxGames, convergence) {
     let simulate = await simulate_wasm();
+    const wasmSeed = seed === undefined ? undefined : BigInt(seed);
+    const simulator = new simulate.Simulator(input, wasmSeed);
+
+    const finished = (async () => {
+        const chunkSize = 1000;
+        const startTime = Date.now();
+        const converged = () => convergence !== undefined && simulator.games() > 0
+            && (simulator.precision() <= convergence.margin || Date.now() - startTime >= convergence.budgetMs);
+        let lastCallback = Date.now();
+        while (!simulator.is_done() && !converged() && (maxGames === undefined || simulator.games() < maxGames)) {
+            if (simulator.is_paused()) {
+                await new Promise(r => setTimeout(r, 100));
+                continue;
+            }
+            simulator.step(maxGames === undefined ? chunkSize : Math.min(chunkSize, maxGames - simulator.games()));
+            if (callback && Date.now() - lastCallback >= 250) {
+                callback(simulator.snapshot());
+                lastCallback = Date.now();
+            }
+            // yield to the browser to keep the UI responsive
+            await new Promise(r => setTimeout(r, 0));
+        }
+        const progress = simulator.snapshot();
+        simulator.free();
+        if (callback) {
+            callback(progress);
+        }
+        return progress.probabilities;
+    })();
 
-    if (callback) {
-        // Use streaming API if callback is provided
-        return await simulate.simulate(input, callback);
-    } else {
-        // Fallback to non-streaming API for backward compatibility
-        return await simulate.simulate(input, () => {});
-    }
+    return {simulator, finished};
+}
+
+// Expected score of every bid per starting position of the simulation of `input`, scoring is one
+// of the SCORING values. The dealer's forbidden bid follows from the seat and bids in the input.
+export async function recommendBids(input, probabilities, scoring) {
+    let simulate = await simulate_wasm();
+    return simulate.recommend(input, probabilities, scoring);
 }
 
-export async function stopSimulation() {
+// Fewest and most tricks of every player when all hands are known, e.g. hands ["As 2h", "Ks 3h"],
+// trump "h" or "x" and leader 0.
+export async function solveDeal(hands, trump, leader) {
     let simulate = await simulate_wasm();
-    return simulate.stop_simulation();
+    return simulate.solve_deal(hands, trump, leader);
 }
 
 export function generateRandomClientId() {
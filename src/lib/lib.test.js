@@ -1,6 +1,7 @@
 import { expect, test, vi } from 'vitest'
 import {
-    calculateGameEarnings, secondPlaceBreaksEven, configurableAmounts
+    calculateGameEarnings, secondPlaceBreaksEven, configurableAmounts, describeViolation,
+    applyScores, isRoundEntered, calculateScoresForGame
 } from './lib.js'
 
 test.each( [
@@ -55,6 +56,8 @@ function gameMock(scores)
             id: i,
             name: `p${i}`,
             score: scores[i],
+            // the serializer breaks ties further, players with the same score keep their order here
+            leaderBoardPosition: scores.filter((score, j) => score > scores[i] || (score === scores[i] && j < i)).length + 1,
         })
     }
 
@@ -114,3 +117,43 @@ test('configurable amounts with custom config', () => {
     let result = calculateGameEarnings(game, allocationFn);
     expect(result).toEqual(expectedMap);
 })
+
+test.each([
+    ['game', {round: null, player: null, rule: {kind: 'noRounds'}}, 'spel heeft geen rondes'],
+    ['round', {round: 2, player: null, rule: {kind: 'tricksDoNotAddUp', total: 11, nCards: 10}}, 'Ronde 3: slagen tellen op tot 11 in plaats van 10'],
+    ['player', {round: 0, player: 1, rule: {kind: 'bidTooHigh', bid: 11, nCards: 10}}, 'Ronde 1, p1: bod 11 is meer dan 10 kaarten'],
+])('describe %s violation', (desc, violation, expected) => {
+    expect(describeViolation(gameMock([0, 0, 0]), violation)).toEqual(expected);
+})
+
+test('half entered round is not scored', () => {
+    let game = {
+        players: [0, 1, 2].map(id => ({id, name: `p${id}`})),
+        rounds: [
+            {nCards: 3, trump: 0, bids: [1, 0, 2], tricks: [1, 1, 1], dealerId: 0, totalScore: [6, 1, 1]},
+            // decoded from a link shared halfway through the round
+            {nCards: 2, trump: 1, bids: [1, undefined, 0], tricks: [1, undefined, null], dealerId: 1, totalScore: [0, 0, 0]},
+        ]
+    };
+    // as returned by the serializer's score
+    let scores = {
+        players: [
+            {id: 0, score: 6, nCorrectBids: 1, highestRoundScore: 6, highestRoundTricks: 1, leaderboardPosition: 1, previousLeaderboardPosition: null},
+            {id: 1, score: 1, nCorrectBids: 0, highestRoundScore: 1, highestRoundTricks: 1, leaderboardPosition: 2, previousLeaderboardPosition: null},
+            {id: 2, score: 1, nCorrectBids: 0, highestRoundScore: 1, highestRoundTricks: 1, leaderboardPosition: 3, previousLeaderboardPosition: null},
+        ],
+        rounds: [{scores: [6, 1, 1], totalScores: [6, 1, 1]}, undefined],
+    };
+
+    applyScores(game, scores);
+
+    expect(game.rounds.map(round => isRoundEntered(game, round))).toEqual([true, false]);
+    expect(game.players.map(p => p.score)).toEqual([6, 1, 1]);
+    expect(game.players.map(p => p.leaderBoardPosition)).toEqual([1, 2, 3]);
+    expect(game.rounds[0].totalScore).toEqual([6, 1, 1]);
+    expect(game.rounds[1].totalScore).toBeUndefined();
+})
+
+test('scores need the serializer to be loaded', () => {
+    expect(() => calculateScoresForGame(gameMock([0, 0, 0]))).toThrow('serializer not loaded');
+})
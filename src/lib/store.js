@@ -4,10 +4,11 @@ import {migrateDealerId, migrateTrumps} from "./migrations.js";
 import {
     calculateScoresForGame,
     currentRoundForGame,
+    isEntered,
     initialGame,
     initialRound,
     GAME_VERSION,
-    deserializeGame, serializeGame, CONFIG_VERSION, generateRandomClientId
+    deserializeGame, serializeGame, validateGame, describeViolation, CONFIG_VERSION, generateRandomClientId
 } from "./lib.js";
 
 const localStorageKey = 'heen-en-weer-store';
@@ -85,9 +86,9 @@ function getGameNumber(gameId, games) {
 }
 
 // Add a new game to the store
-export function addGame(id, name) {
+export function addGame(id, name, scoring) {
     gameStore.update(store => {
-        const game = initialGame(id, name);
+        const game = initialGame(id, name, scoring);
         store.games = [...store.games, game];
         // Clear cache since games array changed
         gameNumberCache.clear();
@@ -118,14 +119,15 @@ export function getGame(gameId) {
     const store = get(gameStore);
     let game = _getGameFromId(store, gameId)
     if (game.gameVersion === undefined || game.gameVersion < GAME_VERSION) {
-        calculateScoresForGame(game);
-        if (game.gameVersion < 2) {
+        if (game.gameVersion === undefined || game.gameVersion < 2) {
             migrateTrumps(game);
         }
-        if (game.gameVersion < 3) {
+        if (game.gameVersion === undefined || game.gameVersion < 3) {
             migrateDealerId(game)
         }
         game.gameVersion = GAME_VERSION;
+        // scored after migrating, the serializer only reads games in the current version
+        calculateScoresForGame(game);
         saveGame(gameId, game);
     }
 
@@ -143,8 +145,35 @@ export function saveGame(id, game) {
     });
 }
 
+// Descriptions of everything wrong with a game, empty when it follows the rules. Games that break a rule can still
+// be shared and exported, serializeGame only fails on values that do not fit in the format.
+export async function gameViolations(game) {
+    let violations = await validateGame(game);
+    return violations.map(violation => describeViolation(game, violation));
+}
+
+// Descriptions of everything wrong with a round when its bids or tricks are replaced, checked before saving them.
+// Earlier rounds are not checked again, so a mistake in an old game does not block the rest of it.
+export async function roundViolations(gameId, roundId, bids, tricks) {
+    let game = getGame(gameId);
+    if (bids !== undefined) {
+        game.rounds[roundId].bids = bids;
+    }
+    if (tricks !== undefined) {
+        game.rounds[roundId].tricks = tricks;
+    }
+    let violations = await validateGame(game);
+    return violations
+        .filter(violation => violation.round === roundId)
+        .map(violation => describeViolation(game, violation));
+}
+
 export async function shareGame(gameId) {
     let game = getGame(gameId);
+    let violations = await gameViolations(game);
+    if (violations.length > 0) {
+        console.warn(`Sharing game ${gameId} with violations:\n${violations.join('\n')}`);
+    }
     let serialized = await serializeGame(game);
     return Base64.fromUint8Array(serialized, true);
 }
@@ -277,7 +306,7 @@ export function getStandingsForGame(game) {
             const previousRound = game.rounds[currentRoundIndex - 1];
             const previousPreviousRound = game.rounds[currentRoundIndex - 2];
             lastRoundScore =  previousRound.totalScore[player.id] - (previousPreviousRound ? previousPreviousRound.totalScore[player.id] : 0);
-            lastRoundBonus = previousRound.bids[player.id] === previousRound.tricks[player.id];
+            lastRoundBonus = isEntered(previousRound.bids[player.id]) && previousRound.bids[player.id] === previousRound.tricks[player.id];
         }
 
         return {
@@ -388,11 +417,12 @@ export async function exportAllGames() {
             migrateDealerId(game);
             saveGame(game.id, game);
 
+            const warnings = await gameViolations(game);
             const serialized = await serializeGame(game);
-            exportResults.push({game, error: null});
+            exportResults.push({game, error: null, warnings});
             return Base64.fromUint8Array(serialized, true);
         } catch (e) {
-            exportResults.push({game, error: e});
+            exportResults.push({game, error: e, warnings: []});
             return null;
         }
 
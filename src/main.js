@@ -1,8 +1,10 @@
 import './app.css'
 import App from './App.svelte'
+import {loadSerializer} from './lib/lib.js'
 
-const app = new App({
-  target: document.getElementById('app'),
+// Scores are calculated by the serializer, it has to be loaded before the first game is shown.
+loadSerializer().then(() => {
+  new App({
+    target: document.getElementById('app'),
+  })
 })
-
-export default app